@@ -0,0 +1,291 @@
+//! Render time of the flattened `Bvh` against the pointer-based `BVHNode` it
+//! replaced, over full `ray_color` paths of the final and random scenes.
+//!
+//! Run with `cargo bench --bench bvh`.
+
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rust_raytracing::{
+    aabb::Aabb,
+    bvh::Bvh,
+    hittable::{HitRecord, Hittable, HittablePtr},
+    ray::Ray,
+    render, rng,
+    rng::RenderRng,
+    scenes::{Scene, SceneConfig},
+    vec3::Point3,
+};
+
+const IMAGE_WIDTH: u32 = 200;
+const SAMPLES_PER_PIXEL: u32 = 4;
+/// Renders of each tree, of which the fastest counts.
+const RUNS: usize = 3;
+
+/// Number of buckets primitive centroids are sorted into along each axis when
+/// looking for the cheapest split.
+const SAH_BIN_COUNT: usize = 16;
+/// Cost of traversing an interior node relative to intersecting a primitive.
+const SAH_TRAVERSAL_COST: f64 = 0.125;
+/// Leaves never hold more primitives than this.
+const MAX_PRIMITIVES_PER_LEAF: usize = 4;
+
+/// Bounding volume hierarchy built with the surface area heuristic, as a tree
+/// of boxed nodes traversed by recursion, the way it was before `Bvh`.
+struct BVHNode {
+    bounding_box: Aabb,
+    contents: BVHContents,
+}
+
+enum BVHContents {
+    Leaf(Vec<HittablePtr>),
+    Interior {
+        left: Box<BVHNode>,
+        right: Box<BVHNode>,
+    },
+}
+
+struct Primitive {
+    object: HittablePtr,
+    bounding_box: Aabb,
+    centroid: Point3,
+}
+
+impl BVHNode {
+    fn new(source_objects: &[HittablePtr], time0: f64, time1: f64) -> BVHNode {
+        let primitives: Vec<Primitive> = source_objects
+            .iter()
+            .map(|object| {
+                let bounding_box = object
+                    .bounding_box(time0, time1)
+                    .unwrap_or_else(Aabb::empty);
+                Primitive {
+                    object: object.clone(),
+                    bounding_box,
+                    centroid: bounding_box.centroid(),
+                }
+            })
+            .collect();
+
+        BVHNode::build(primitives)
+    }
+
+    fn build(mut primitives: Vec<Primitive>) -> BVHNode {
+        let bounding_box = surrounding_box(primitives.iter().map(|p| p.bounding_box));
+
+        let count = primitives.len();
+        if count <= 1 {
+            return BVHNode::leaf(primitives, bounding_box);
+        }
+
+        let centroid_box =
+            surrounding_box(primitives.iter().map(|p| Aabb::new(p.centroid, p.centroid)));
+
+        // Find the cheapest split over all axes using binned centroids.
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            let min = centroid_box.min()[axis];
+            let extent = centroid_box.max()[axis] - min;
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bin_counts = [0usize; SAH_BIN_COUNT];
+            let mut bin_boxes: [Option<Aabb>; SAH_BIN_COUNT] = [None; SAH_BIN_COUNT];
+            for primitive in &primitives {
+                let bin = bin_index(primitive.centroid[axis], min, extent);
+                bin_counts[bin] += 1;
+                bin_boxes[bin] = Some(match bin_boxes[bin] {
+                    Some(b) => b.surrounding_box(&primitive.bounding_box),
+                    None => primitive.bounding_box,
+                });
+            }
+
+            // Sweep from the right to get the area and count of every suffix.
+            let mut right_areas = [0.0; SAH_BIN_COUNT];
+            let mut right_counts = [0usize; SAH_BIN_COUNT];
+            let mut right_box: Option<Aabb> = None;
+            let mut right_count = 0;
+            for bin in (1..SAH_BIN_COUNT).rev() {
+                right_box = union(right_box, bin_boxes[bin]);
+                right_count += bin_counts[bin];
+                right_areas[bin] = right_box.map_or(0.0, |b| b.surface_area());
+                right_counts[bin] = right_count;
+            }
+
+            // Sweep from the left, splitting between `bin - 1` and `bin`.
+            let mut left_box: Option<Aabb> = None;
+            let mut left_count = 0;
+            for bin in 1..SAH_BIN_COUNT {
+                left_box = union(left_box, bin_boxes[bin - 1]);
+                left_count += bin_counts[bin - 1];
+                if left_count == 0 || right_counts[bin] == 0 {
+                    continue;
+                }
+                let left_area = left_box.map_or(0.0, |b| b.surface_area());
+                let cost =
+                    left_count as f64 * left_area + right_counts[bin] as f64 * right_areas[bin];
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, bin));
+                }
+            }
+        }
+
+        let parent_area = bounding_box.surface_area();
+        let split = best.and_then(|(cost, axis, bin)| {
+            let split_cost = if parent_area > 0.0 {
+                SAH_TRAVERSAL_COST + cost / parent_area
+            } else {
+                0.0
+            };
+            // Splitting has to pay for itself unless the leaf would be too big.
+            if split_cost < count as f64 || count > MAX_PRIMITIVES_PER_LEAF {
+                Some((axis, bin))
+            } else {
+                None
+            }
+        });
+
+        let right_primitives = match split {
+            Some((axis, bin)) => {
+                let min = centroid_box.min()[axis];
+                let extent = centroid_box.max()[axis] - min;
+                let (left, right): (Vec<Primitive>, Vec<Primitive>) = primitives
+                    .into_iter()
+                    .partition(|p| bin_index(p.centroid[axis], min, extent) < bin);
+                primitives = left;
+                right
+            }
+            // All centroids coincide, so no split separates anything. Halve
+            // the list if it is too big for a single leaf.
+            None if count > MAX_PRIMITIVES_PER_LEAF => primitives.split_off(count / 2),
+            None => return BVHNode::leaf(primitives, bounding_box),
+        };
+
+        BVHNode {
+            bounding_box,
+            contents: BVHContents::Interior {
+                left: Box::new(BVHNode::build(primitives)),
+                right: Box::new(BVHNode::build(right_primitives)),
+            },
+        }
+    }
+
+    fn leaf(primitives: Vec<Primitive>, bounding_box: Aabb) -> BVHNode {
+        BVHNode {
+            bounding_box,
+            contents: BVHContents::Leaf(primitives.into_iter().map(|p| p.object).collect()),
+        }
+    }
+}
+
+fn bin_index(value: f64, min: f64, extent: f64) -> usize {
+    let bin = (SAH_BIN_COUNT as f64 * (value - min) / extent) as usize;
+    bin.min(SAH_BIN_COUNT - 1)
+}
+
+fn union(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.surrounding_box(&b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn surrounding_box(boxes: impl Iterator<Item = Aabb>) -> Aabb {
+    boxes
+        .map(Some)
+        .reduce(union)
+        .flatten()
+        .unwrap_or_else(Aabb::empty)
+}
+
+impl Hittable for BVHNode {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
+        if !self.bounding_box.hit(ray, t_min, t_max) {
+            return false;
+        }
+
+        match &self.contents {
+            BVHContents::Leaf(objects) => {
+                let mut hit_anything = false;
+                let mut closest_so_far = t_max;
+                for object in objects {
+                    if object.hit(ray, t_min, closest_so_far, rec, rng) {
+                        hit_anything = true;
+                        closest_so_far = rec.t;
+                    }
+                }
+                hit_anything
+            }
+            BVHContents::Interior { left, right } => {
+                let hit_left = left.hit(ray, t_min, t_max, rec, rng);
+                let right_t_max = if hit_left { rec.t } else { t_max };
+                let hit_right = right.hit(ray, t_min, right_t_max, rec, rng);
+
+                hit_left || hit_right
+            }
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bounding_box)
+    }
+}
+
+/// Time to render `scene` on one thread with `world` in place of its objects.
+fn render_time(scene: &SceneConfig, world: &impl Hittable) -> Duration {
+    let width = IMAGE_WIDTH;
+    let height = rust_raytracing::scenes::image_height(width, scene.aspect_ratio);
+    let start = Instant::now();
+    for y in 0..height {
+        for x in 0..width {
+            for sample in 0..SAMPLES_PER_PIXEL {
+                let mut rng = rng::sample_rng(scene.seed, x, y, sample);
+                let u = (x as f64 + rng.gen::<f64>()) / (width - 1) as f64;
+                let v = (y as f64 + rng.gen::<f64>()) / (height - 1) as f64;
+                let ray = scene.camera.get_ray(u, v, &mut rng);
+                render::ray_color(
+                    &ray,
+                    &scene.background,
+                    world,
+                    &scene.lights,
+                    scene.max_depth,
+                    &mut rng,
+                );
+            }
+        }
+    }
+    start.elapsed()
+}
+
+fn main() {
+    for scene in [Scene::FinalScene, Scene::Random] {
+        let config = SceneConfig::get_scene(&scene);
+        let (time0, time1) = (
+            config.camera.shutter_open_time(),
+            config.camera.shutter_close_time(),
+        );
+        let flat = Bvh::new(&config.world.objects, time0, time1);
+        let recursive = BVHNode::new(&config.world.objects, time0, time1);
+
+        let mut recursive_time = Duration::MAX;
+        let mut flat_time = Duration::MAX;
+        for _ in 0..RUNS {
+            recursive_time = recursive_time.min(render_time(&config, &recursive));
+            flat_time = flat_time.min(render_time(&config, &flat));
+        }
+
+        println!(
+            "{scene:?}: BVHNode {recursive_time:.2?}, Bvh {flat_time:.2?} ({:.2}x faster)",
+            recursive_time.as_secs_f64() / flat_time.as_secs_f64()
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{ray::Ray, vec3::Point3};
+use crate::{
+    matrix::Matrix4,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
 
 #[derive(Copy, Clone)]
 pub struct Aabb {
@@ -27,7 +31,7 @@ impl Aabb {
         &self.maximum
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+    pub fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
         for a in 0..3 {
             let inv_d = 1.0 / ray.direction[a];
             let mut t0 = (self.minimum[a] - ray.origin[a]) * inv_d;
@@ -35,8 +39,8 @@ impl Aabb {
             if inv_d < 0.0 {
                 (t0, t1) = (t1, t0);
             }
-            let t_min = if t0 > t_min { t0 } else { t_min };
-            let t_max = if t1 < t_max { t1 } else { t_max };
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
             if t_max <= t_min {
                 return false;
             }
@@ -45,6 +49,15 @@ impl Aabb {
         true
     }
 
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.maximum - self.minimum;
+        2.0 * (extent.x() * extent.y() + extent.y() * extent.z() + extent.z() * extent.x())
+    }
+
     pub fn surrounding_box(&self, other: &Aabb) -> Aabb {
         let small = Point3::new(
             self.min().x().min(other.min().x()),
@@ -59,6 +72,39 @@ impl Aabb {
 
         Aabb::new(small, big)
     }
+
+    pub fn corners(&self) -> [Point3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            let pick = |bit, axis: usize| {
+                if i & bit == 0 {
+                    self.minimum[axis]
+                } else {
+                    self.maximum[axis]
+                }
+            };
+            Point3::new(pick(1, 0), pick(2, 1), pick(4, 2))
+        })
+    }
+
+    /// Smallest box enclosing this one after transforming it by `matrix`.
+    pub fn transformed(&self, matrix: &Matrix4) -> Aabb {
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+        for corner in self.corners() {
+            let corner = matrix.transform_point(&corner);
+            for c in 0..3 {
+                min[c] = min[c].min(corner[c]);
+                max[c] = max[c].max(corner[c]);
+            }
+        }
+        Aabb::new(min, max)
+    }
+
+    /// This box grown by `padding` on every side.
+    pub fn padded(&self, padding: f64) -> Aabb {
+        let padding = Vec3::new(padding, padding, padding);
+        Aabb::new(self.minimum - padding, self.maximum + padding)
+    }
 }
 
 #[cfg(test)]
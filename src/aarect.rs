@@ -1,10 +1,14 @@
 use std::sync::Arc;
 
+use rand::Rng;
+
 use crate::{
     aabb::Aabb,
     hittable::{HitRecord, Hittable},
+    inspector::{self, Node, Part},
     material::MaterialPtr,
     ray::Ray,
+    rng::{self, RenderRng},
     vec3::{Point3, Vec3},
 };
 
@@ -85,7 +89,14 @@ impl Hittable for XYRect {
         Some(output_box)
     }
 
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _rng: &mut RenderRng,
+    ) -> bool {
         let t = (self.k - ray.origin.z()) / ray.direction.z();
         if t < t_min || t > t_max {
             return false;
@@ -105,6 +116,57 @@ impl Hittable for XYRect {
         rec.p = ray.at(t);
         true
     }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let mut rec = HitRecord::empty();
+        if !self.hit(
+            &Ray::new(*origin, *direction, time),
+            0.001,
+            f64::INFINITY,
+            &mut rec,
+            &mut rng::unused_rng(),
+        ) {
+            return 0.0;
+        }
+
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, rng: &mut RenderRng) -> Vec3 {
+        let random_point = Point3::new(
+            rng.gen_range(self.x0..self.x1),
+            rng.gen_range(self.y0..self.y1),
+            self.k,
+        );
+        random_point - origin
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let (x0, x1, y0, y1, k) = (self.x0, self.x1, self.y0, self.y1, self.k);
+        Some(Node::new(
+            "Rectangle",
+            vec![],
+            vec![inspector::material("Material", &self.material)],
+            move |_, parts| {
+                Part::Object(Arc::new(XYRect::new(
+                    x0,
+                    x1,
+                    y0,
+                    y1,
+                    k,
+                    &parts[0].material(),
+                )))
+            },
+        ))
+    }
 }
 
 impl Hittable for XZRect {
@@ -120,7 +182,14 @@ impl Hittable for XZRect {
         Some(output_box)
     }
 
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _rng: &mut RenderRng,
+    ) -> bool {
         let t = (self.k - ray.origin.y()) / ray.direction.y();
         if t < t_min || t > t_max {
             return false;
@@ -140,6 +209,57 @@ impl Hittable for XZRect {
         rec.p = ray.at(t);
         true
     }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let mut rec = HitRecord::empty();
+        if !self.hit(
+            &Ray::new(*origin, *direction, time),
+            0.001,
+            f64::INFINITY,
+            &mut rec,
+            &mut rng::unused_rng(),
+        ) {
+            return 0.0;
+        }
+
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, rng: &mut RenderRng) -> Vec3 {
+        let random_point = Point3::new(
+            rng.gen_range(self.x0..self.x1),
+            self.k,
+            rng.gen_range(self.z0..self.z1),
+        );
+        random_point - origin
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let (x0, x1, z0, z1, k) = (self.x0, self.x1, self.z0, self.z1, self.k);
+        Some(Node::new(
+            "Rectangle",
+            vec![],
+            vec![inspector::material("Material", &self.material)],
+            move |_, parts| {
+                Part::Object(Arc::new(XZRect::new(
+                    x0,
+                    x1,
+                    z0,
+                    z1,
+                    k,
+                    &parts[0].material(),
+                )))
+            },
+        ))
+    }
 }
 
 impl Hittable for YZRect {
@@ -155,7 +275,14 @@ impl Hittable for YZRect {
         Some(output_box)
     }
 
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _rng: &mut RenderRng,
+    ) -> bool {
         let t = (self.k - ray.origin.x()) / ray.direction.x();
         if t < t_min || t > t_max {
             return false;
@@ -175,4 +302,55 @@ impl Hittable for YZRect {
         rec.p = ray.at(t);
         true
     }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let mut rec = HitRecord::empty();
+        if !self.hit(
+            &Ray::new(*origin, *direction, time),
+            0.001,
+            f64::INFINITY,
+            &mut rec,
+            &mut rng::unused_rng(),
+        ) {
+            return 0.0;
+        }
+
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, rng: &mut RenderRng) -> Vec3 {
+        let random_point = Point3::new(
+            self.k,
+            rng.gen_range(self.y0..self.y1),
+            rng.gen_range(self.z0..self.z1),
+        );
+        random_point - origin
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let (y0, y1, z0, z1, k) = (self.y0, self.y1, self.z0, self.z1, self.k);
+        Some(Node::new(
+            "Rectangle",
+            vec![],
+            vec![inspector::material("Material", &self.material)],
+            move |_, parts| {
+                Part::Object(Arc::new(YZRect::new(
+                    y0,
+                    y1,
+                    z0,
+                    z1,
+                    k,
+                    &parts[0].material(),
+                )))
+            },
+        ))
+    }
 }
@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable, HittablePtr},
+    inspector::{self, Node, Part},
+    matrix::Matrix4,
+    quaternion::Quaternion,
+    ray::Ray,
+    rng::RenderRng,
+    vec3::{self, Point3, Vec3},
+};
+
+/// Largest rotation between two of the poses sampled to bound an animation.
+const MAX_BOUNDS_STEP: f64 = 5.0 * std::f64::consts::PI / 180.0;
+
+/// Placement of an object at one point in time: scaled, then rotated, then
+/// translated.
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub scale: Vec3,
+    pub rotation: Quaternion,
+    pub translation: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, scale: Vec3, rotation: Quaternion, translation: Vec3) -> Keyframe {
+        Keyframe {
+            time,
+            scale,
+            rotation,
+            translation,
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4 {
+        Matrix4::translation(&self.translation)
+            * self.rotation.to_matrix()
+            * Matrix4::scaling(&self.scale)
+    }
+
+    /// Inverse of `matrix`, without a general matrix inversion.
+    pub fn inverse_matrix(&self) -> Matrix4 {
+        let inverse_scale = Vec3::new(
+            1.0 / self.scale.x(),
+            1.0 / self.scale.y(),
+            1.0 / self.scale.z(),
+        );
+        Matrix4::scaling(&inverse_scale)
+            * self.rotation.conjugate().to_matrix()
+            * Matrix4::translation(&-&self.translation)
+    }
+
+    /// Linear interpolation of scale and translation and spherical
+    /// interpolation of rotation, with `t` from 0 at `self` to 1 at `other`.
+    fn interpolate(&self, other: &Keyframe, t: f64) -> Keyframe {
+        Keyframe {
+            time: self.time + t * (other.time - self.time),
+            scale: self.scale + t * (other.scale - self.scale),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            translation: self.translation + t * (other.translation - self.translation),
+        }
+    }
+}
+
+/// Keyframed motion, holding the first and last poses before and after the
+/// keyframes.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Animation {
+    /// Panics if `keyframes` is empty.
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Animation {
+        assert!(!keyframes.is_empty(), "Animation needs a keyframe");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Animation { keyframes }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// The pose at `time`.
+    pub fn at(&self, time: f64) -> Keyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+
+        let (start, end) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        start.interpolate(end, (time - start.time) / (end.time - start.time))
+    }
+
+    /// Box enclosing `bbox` in every pose between `time0` and `time1`.
+    ///
+    /// Poses are sampled closely enough that the arc a point rotates along
+    /// between two samples strays from the straight line between them by at
+    /// most a small fraction of its distance from the center, which the box
+    /// is padded by.
+    pub fn bounding_box(&self, bbox: &Aabb, time0: f64, time1: f64) -> Aabb {
+        let mut times = vec![time0];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.time)
+                .filter(|&t| t > time0 && t < time1),
+        );
+        times.push(time1);
+
+        let mut output = bbox.transformed(&self.at(time0).matrix());
+        for segment in times.windows(2) {
+            let (start, end) = (self.at(segment[0]), self.at(segment[1]));
+            let steps = (start.rotation.angle_to(&end.rotation) / MAX_BOUNDS_STEP).ceil() as u32;
+            for step in 1..=steps.max(1) {
+                let pose = start.interpolate(&end, step as f64 / steps.max(1) as f64);
+                output = output.surrounding_box(&bbox.transformed(&pose.matrix()));
+            }
+        }
+
+        let max_scale = self
+            .keyframes
+            .iter()
+            .flat_map(|k| k.scale.e)
+            .fold(0.0, |max: f64, s| max.max(s.abs()));
+        let radius = bbox
+            .corners()
+            .iter()
+            .map(|corner| corner.length())
+            .fold(0.0, f64::max);
+        output.padded(max_scale * radius * (1.0 - (MAX_BOUNDS_STEP / 2.0).cos()))
+    }
+}
+
+/// Instance of a hittable moving along an [`Animation`], so that it blurs
+/// over the camera shutter interval.
+pub struct AnimatedTransform {
+    hittable: HittablePtr,
+    animation: Animation,
+}
+
+impl AnimatedTransform {
+    pub fn new(hittable: &HittablePtr, animation: Animation) -> AnimatedTransform {
+        AnimatedTransform {
+            hittable: Arc::clone(hittable),
+            animation,
+        }
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.hittable
+            .bounding_box(time0, time1)
+            .map(|bbox| self.animation.bounding_box(&bbox, time0, time1))
+    }
+
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
+        let pose = self.animation.at(ray.time);
+        let inverse = pose.inverse_matrix();
+        let local_ray = Ray::new(
+            inverse.transform_point(&ray.origin),
+            inverse.transform_vector(&ray.direction),
+            ray.time,
+        );
+        if !self.hittable.hit(&local_ray, t_min, t_max, rec, rng) {
+            return false;
+        }
+
+        rec.p = pose.matrix().transform_point(&rec.p);
+        rec.normal = vec3::unit_vector(&inverse.transpose().transform_vector(&rec.normal));
+
+        true
+    }
+
+    fn can_sample(&self) -> bool {
+        self.hittable.can_sample()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let inverse = self.animation.at(time).inverse_matrix();
+        let local_direction = inverse.transform_vector(&vec3::unit_vector(direction));
+        let local_pdf =
+            self.hittable
+                .pdf_value(&inverse.transform_point(origin), &local_direction, time);
+        // As for `Transform`, scaling spreads directions over more or less
+        // solid angle.
+        local_pdf * inverse.linear_determinant().abs() / local_direction.length().powi(3)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut RenderRng) -> Vec3 {
+        let pose = self.animation.at(time);
+        let local_origin = pose.inverse_matrix().transform_point(origin);
+        pose.matrix()
+            .transform_vector(&self.hittable.random(&local_origin, time, rng))
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let animation = self.animation.clone();
+        Some(Node::new(
+            "Animated transform",
+            vec![],
+            vec![inspector::object("Object", &self.hittable)],
+            move |_, parts| {
+                Part::Object(Arc::new(AnimatedTransform::new(
+                    &parts[0].object(),
+                    animation.clone(),
+                )))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        box_struct,
+        material::{DiffuseLight, Lambertian, MaterialPtr},
+        rng,
+        sphere::Sphere,
+    };
+
+    #[test]
+    fn test_spinning_box() {
+        let material: MaterialPtr = Arc::new(Lambertian::new_from_color(&Vec3::new(0.5, 0.5, 0.5)));
+        let unit_box: HittablePtr = Arc::new(box_struct::Box::new(
+            &Point3::new(-1.0, -1.0, -1.0),
+            &Point3::new(1.0, 1.0, 1.0),
+            &material,
+        ));
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        let animation = Animation::new(vec![
+            Keyframe::new(
+                1.0,
+                one,
+                Quaternion::from_axis_angle(&y, 90.0),
+                Vec3::origin(),
+            ),
+            Keyframe::new(0.0, one, Quaternion::identity(), Vec3::new(0.0, 0.0, -5.0)),
+        ]);
+        let spinning = AnimatedTransform::new(&unit_box, animation);
+
+        // Half way the box is turned by 45 degrees, so a corner faces +z.
+        let mut rec = HitRecord::empty();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.5);
+        assert!(spinning.hit(&ray, 0.001, f64::INFINITY, &mut rec, &mut rng::unused_rng()));
+        assert!((rec.p.z() - (2f64.sqrt() - 2.5)).abs() < 1e-9);
+        assert!(rec.front_face);
+
+        // The box over the whole motion holds the corner sticking out at
+        // 45 degrees, which neither end pose reaches.
+        let bbox = spinning.bounding_box(0.0, 1.0).unwrap();
+        assert!(bbox.max().x() >= 2f64.sqrt());
+        assert!(bbox.max().x() < 2f64.sqrt() + 0.01);
+        assert!(bbox.min().z() <= -6.0);
+        assert!(bbox.max().z() >= 1.0);
+    }
+
+    #[test]
+    fn test_moving_light() {
+        let material: MaterialPtr =
+            Arc::new(DiffuseLight::new_from_color(&Vec3::new(1.0, 1.0, 1.0)));
+        let sphere: HittablePtr = Arc::new(Sphere::new(Point3::origin(), 1.0, &material));
+        let stretch = Vec3::new(1.5, 1.0, 1.0);
+        let animation = Animation::new(vec![
+            Keyframe::new(
+                0.0,
+                stretch,
+                Quaternion::identity(),
+                Vec3::new(0.0, 0.0, -5.0),
+            ),
+            Keyframe::new(
+                1.0,
+                stretch,
+                Quaternion::identity(),
+                Vec3::new(6.0, 0.0, -5.0),
+            ),
+        ]);
+        let light = AnimatedTransform::new(&sphere, animation);
+        assert!(light.can_sample());
+
+        // Directions are sampled towards the light where it is at the time.
+        let mut rng = rng::sample_rng(0, 0, 0, 0);
+        let origin = Point3::origin();
+        for _ in 0..100 {
+            let direction = light.random(&origin, 1.0, &mut rng);
+            let ray = Ray::new(origin, direction, 1.0);
+            let mut rec = HitRecord::empty();
+            assert!(light.hit(&ray, 0.001, f64::INFINITY, &mut rec, &mut rng));
+            assert!(light.pdf_value(&origin, &direction, 1.0) > 0.0);
+            assert_eq!(light.pdf_value(&origin, &direction, 0.0), 0.0);
+        }
+    }
+}
@@ -5,7 +5,9 @@ use crate::{
     aarect::{XYRect, XZRect, YZRect},
     hittable::Hittable,
     hittable_list::HittableList,
+    inspector::{self, Node, Parameter, Part},
     material::MaterialPtr,
+    rng::RenderRng,
     vec3::Point3,
 };
 
@@ -13,6 +15,7 @@ pub struct Box {
     min: Point3,
     max: Point3,
     sides: HittableList,
+    material: MaterialPtr,
 }
 
 impl Box {
@@ -74,6 +77,7 @@ impl Box {
             min: *min,
             max: *max,
             sides,
+            material: Arc::clone(material),
         }
     }
 }
@@ -89,7 +93,26 @@ impl Hittable for Box {
         t_min: f64,
         t_max: f64,
         rec: &mut crate::hittable::HitRecord,
+        rng: &mut RenderRng,
     ) -> bool {
-        self.sides.hit(ray, t_min, t_max, rec)
+        self.sides.hit(ray, t_min, t_max, rec, rng)
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Box",
+            vec![
+                Parameter::point("Min corner", self.min),
+                Parameter::point("Max corner", self.max),
+            ],
+            vec![inspector::material("Material", &self.material)],
+            |values, parts| {
+                Part::Object(Arc::new(Box::new(
+                    &values[0].point(),
+                    &values[1].point(),
+                    &parts[0].material(),
+                )))
+            },
+        ))
     }
 }
@@ -1,124 +1,430 @@
-use std::sync::Arc;
-
-use rand::Rng;
-
 use crate::{
     aabb::Aabb,
     hittable::{HitRecord, Hittable, HittablePtr},
     hittable_list::HittableList,
+    inspector::Node,
     ray::Ray,
+    rng::RenderRng,
+    vec3::Point3,
 };
 
-pub struct BVHNode {
-    pub left: HittablePtr,
-    pub right: HittablePtr,
-    pub bounding_box: Aabb,
+/// Number of buckets primitive centroids are sorted into along each axis when
+/// looking for the cheapest split.
+const SAH_BIN_COUNT: usize = 16;
+/// Cost of traversing an interior node relative to intersecting a primitive.
+const SAH_TRAVERSAL_COST: f64 = 0.125;
+/// Leaves never hold more primitives than this, unless the tree gets too deep.
+const MAX_PRIMITIVES_PER_LEAF: usize = 4;
+/// Bound on the tree depth, which is also the size of the traversal stack.
+const MAX_DEPTH: usize = 64;
+
+/// Bounding volume hierarchy built with the surface area heuristic.
+///
+/// The tree is stored flattened in depth-first order: the first child of an
+/// interior node directly follows it and the node stores the index of the
+/// second child. Traversal walks the nodes with an explicit stack, front to
+/// back along the axis each node is split on.
+pub struct Bvh {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<HittablePtr>,
+    stats: BvhStats,
 }
 
-impl BVHNode {
-    pub fn new(source_objects: &[HittablePtr], time0: f64, time1: f64) -> BVHNode {
-        let mut my_objects = Vec::new();
+struct LinearNode {
+    bounding_box: Aabb,
+    /// Index of the first primitive for leaves, index of the second child for
+    /// interior nodes.
+    offset: u32,
+    /// Zero for interior nodes.
+    primitive_count: u32,
+    /// Axis the children of an interior node are split along, the first
+    /// child holding the lower side.
+    axis: u8,
+}
 
-        for object in source_objects {
-            my_objects.push(Arc::clone(object));
+/// Shape of a BVH, for judging its quality.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BvhStats {
+    pub depth: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub primitive_count: usize,
+}
+
+impl BvhStats {
+    pub fn average_primitives_per_leaf(&self) -> f64 {
+        if self.leaf_count == 0 {
+            0.0
+        } else {
+            self.primitive_count as f64 / self.leaf_count as f64
         }
+    }
+}
 
-        let mut rng = rand::thread_rng();
-        let axis = rng.gen_range(0..=2);
+impl std::fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} primitives, {} nodes, {} leaves, depth {}, {:.2} primitives per leaf",
+            self.primitive_count,
+            self.node_count,
+            self.leaf_count,
+            self.depth,
+            self.average_primitives_per_leaf()
+        )
+    }
+}
 
-        let comparator = match axis {
-            0 => box_x_compare,
-            1 => box_y_compare,
-            _ => box_z_compare,
-        };
+struct Primitive {
+    object: HittablePtr,
+    bounding_box: Aabb,
+    centroid: Point3,
+}
 
-        let objects_size = my_objects.len();
+/// Pointer-based tree produced by the builder before it is flattened.
+enum BuildNode {
+    Leaf {
+        bounding_box: Aabb,
+        primitives: Vec<Primitive>,
+    },
+    Interior {
+        bounding_box: Aabb,
+        axis: u8,
+        left: Box<BuildNode>,
+        right: Box<BuildNode>,
+    },
+}
 
-        let (left, right) = match objects_size {
-            1 => (Arc::clone(&my_objects[0]), Arc::clone(&my_objects[0])),
-            2 => {
-                // if my_objects[0] < my_objects[1], else...
-                if comparator(&my_objects[0], &my_objects[1]).is_lt() {
-                    (Arc::clone(&my_objects[0]), Arc::clone(&my_objects[1]))
-                } else {
-                    (Arc::clone(&my_objects[1]), Arc::clone(&my_objects[0]))
+impl Bvh {
+    pub fn new(source_objects: &[HittablePtr], time0: f64, time1: f64) -> Bvh {
+        let primitives: Vec<Primitive> = source_objects
+            .iter()
+            .map(|object| {
+                let bounding_box = object.bounding_box(time0, time1).unwrap_or_else(|| {
+                    eprintln!("No bounding box in Bvh constructor.");
+                    Aabb::empty()
+                });
+                Primitive {
+                    object: object.clone(),
+                    bounding_box,
+                    centroid: bounding_box.centroid(),
                 }
-            }
-            _ => {
-                my_objects.sort_by(comparator);
+            })
+            .collect();
 
-                let mid = objects_size / 2;
-                let left_tmp: HittablePtr =
-                    Arc::new(BVHNode::new(&my_objects[0..mid], time0, time1));
-                let right_tmp: HittablePtr =
-                    Arc::new(BVHNode::new(&my_objects[mid..], time0, time1));
+        let root = BuildNode::build(primitives, 1);
 
-                (left_tmp, right_tmp)
-            }
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            primitives: Vec::with_capacity(source_objects.len()),
+            stats: BvhStats::default(),
         };
+        bvh.flatten(root, 1);
+        bvh
+    }
 
-        let box_left = left.bounding_box(time0, time1);
-        let box_right = right.bounding_box(time0, time1);
+    pub fn from_hittable_list(list: &HittableList, time0: f64, time1: f64) -> Bvh {
+        Bvh::new(&list.objects, time0, time1)
+    }
 
-        if box_left.is_none() || box_right.is_none() {
-            eprintln!("No bounding box in BVHNode constructor.");
-        }
+    pub fn stats(&self) -> BvhStats {
+        self.stats
+    }
 
-        let bounding_box = box_left
-            .unwrap_or_else(Aabb::empty)
-            .surrounding_box(&box_right.unwrap_or_else(Aabb::empty));
+    /// Append `node` and its subtree in depth-first order, returning its index.
+    fn flatten(&mut self, node: BuildNode, depth: usize) -> usize {
+        let index = self.nodes.len();
+        self.stats.node_count += 1;
+        self.stats.depth = self.stats.depth.max(depth);
 
-        BVHNode {
-            left,
-            right,
-            bounding_box,
+        match node {
+            BuildNode::Leaf {
+                bounding_box,
+                primitives,
+            } => {
+                self.stats.leaf_count += 1;
+                self.stats.primitive_count += primitives.len();
+                self.nodes.push(LinearNode {
+                    bounding_box,
+                    offset: self.primitives.len() as u32,
+                    primitive_count: primitives.len() as u32,
+                    axis: 0,
+                });
+                self.primitives
+                    .extend(primitives.into_iter().map(|p| p.object));
+            }
+            BuildNode::Interior {
+                bounding_box,
+                axis,
+                left,
+                right,
+            } => {
+                self.nodes.push(LinearNode {
+                    bounding_box,
+                    offset: 0,
+                    primitive_count: 0,
+                    axis,
+                });
+                self.flatten(*left, depth + 1);
+                let second_child = self.flatten(*right, depth + 1);
+                self.nodes[index].offset = second_child as u32;
+            }
         }
-    }
 
-    pub fn from_hittable_list(list: &HittableList, time0: f64, time1: f64) -> BVHNode {
-        BVHNode::new(&list.objects, time0, time1)
+        index
     }
 }
 
-fn box_compare(a: &HittablePtr, b: &HittablePtr, axis: usize) -> std::cmp::Ordering {
-    let box_a = a.bounding_box(0.0, 0.0);
-    let box_b = b.bounding_box(0.0, 0.0);
+impl BuildNode {
+    fn build(mut primitives: Vec<Primitive>, depth: usize) -> BuildNode {
+        let bounding_box = surrounding_box(primitives.iter().map(|p| p.bounding_box));
 
-    if box_a.is_none() || box_b.is_none() {
-        eprintln!("No bounding box in BVHNode constructor.");
-    }
+        let count = primitives.len();
+        if count <= 1 || depth >= MAX_DEPTH {
+            return BuildNode::Leaf {
+                bounding_box,
+                primitives,
+            };
+        }
+
+        let centroid_box =
+            surrounding_box(primitives.iter().map(|p| Aabb::new(p.centroid, p.centroid)));
+
+        // Find the cheapest split over all axes using binned centroids.
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            let min = centroid_box.min()[axis];
+            let extent = centroid_box.max()[axis] - min;
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bin_counts = [0usize; SAH_BIN_COUNT];
+            let mut bin_boxes: [Option<Aabb>; SAH_BIN_COUNT] = [None; SAH_BIN_COUNT];
+            for primitive in &primitives {
+                let bin = bin_index(primitive.centroid[axis], min, extent);
+                bin_counts[bin] += 1;
+                bin_boxes[bin] = Some(match bin_boxes[bin] {
+                    Some(b) => b.surrounding_box(&primitive.bounding_box),
+                    None => primitive.bounding_box,
+                });
+            }
+
+            // Sweep from the right to get the area and count of every suffix.
+            let mut right_areas = [0.0; SAH_BIN_COUNT];
+            let mut right_counts = [0usize; SAH_BIN_COUNT];
+            let mut right_box: Option<Aabb> = None;
+            let mut right_count = 0;
+            for bin in (1..SAH_BIN_COUNT).rev() {
+                right_box = union(right_box, bin_boxes[bin]);
+                right_count += bin_counts[bin];
+                right_areas[bin] = right_box.map_or(0.0, |b| b.surface_area());
+                right_counts[bin] = right_count;
+            }
+
+            // Sweep from the left, splitting between `bin - 1` and `bin`.
+            let mut left_box: Option<Aabb> = None;
+            let mut left_count = 0;
+            for bin in 1..SAH_BIN_COUNT {
+                left_box = union(left_box, bin_boxes[bin - 1]);
+                left_count += bin_counts[bin - 1];
+                if left_count == 0 || right_counts[bin] == 0 {
+                    continue;
+                }
+                let left_area = left_box.map_or(0.0, |b| b.surface_area());
+                let cost =
+                    left_count as f64 * left_area + right_counts[bin] as f64 * right_areas[bin];
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, bin));
+                }
+            }
+        }
+
+        let parent_area = bounding_box.surface_area();
+        let split = best.and_then(|(cost, axis, bin)| {
+            let split_cost = if parent_area > 0.0 {
+                SAH_TRAVERSAL_COST + cost / parent_area
+            } else {
+                0.0
+            };
+            // Splitting has to pay for itself unless the leaf would be too big.
+            if split_cost < count as f64 || count > MAX_PRIMITIVES_PER_LEAF {
+                Some((axis, bin))
+            } else {
+                None
+            }
+        });
+
+        let (axis, right_primitives) = match split {
+            Some((axis, bin)) => {
+                let min = centroid_box.min()[axis];
+                let extent = centroid_box.max()[axis] - min;
+                let (left, right): (Vec<Primitive>, Vec<Primitive>) = primitives
+                    .into_iter()
+                    .partition(|p| bin_index(p.centroid[axis], min, extent) < bin);
+                primitives = left;
+                (axis, right)
+            }
+            // All centroids coincide, so no split separates anything. Halve
+            // the list if it is too big for a single leaf, in no order along
+            // any axis.
+            None if count > MAX_PRIMITIVES_PER_LEAF => (0, primitives.split_off(count / 2)),
+            None => {
+                return BuildNode::Leaf {
+                    bounding_box,
+                    primitives,
+                }
+            }
+        };
 
-    box_a
-        .map_or_else(|| 0.0, |b| b.min().e[axis])
-        .total_cmp(&box_b.map_or_else(|| 0.0, |b| b.min().e[axis]))
+        BuildNode::Interior {
+            bounding_box,
+            axis: axis as u8,
+            left: Box::new(BuildNode::build(primitives, depth + 1)),
+            right: Box::new(BuildNode::build(right_primitives, depth + 1)),
+        }
+    }
 }
 
-fn box_x_compare(a: &HittablePtr, b: &HittablePtr) -> std::cmp::Ordering {
-    box_compare(a, b, 0)
+fn bin_index(value: f64, min: f64, extent: f64) -> usize {
+    let bin = (SAH_BIN_COUNT as f64 * (value - min) / extent) as usize;
+    bin.min(SAH_BIN_COUNT - 1)
 }
 
-fn box_y_compare(a: &HittablePtr, b: &HittablePtr) -> std::cmp::Ordering {
-    box_compare(a, b, 1)
+fn union(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.surrounding_box(&b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
 }
 
-fn box_z_compare(a: &HittablePtr, b: &HittablePtr) -> std::cmp::Ordering {
-    box_compare(a, b, 2)
+fn surrounding_box(boxes: impl Iterator<Item = Aabb>) -> Aabb {
+    boxes
+        .map(Some)
+        .reduce(union)
+        .flatten()
+        .unwrap_or_else(Aabb::empty)
 }
 
-impl Hittable for BVHNode {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        if !self.bounding_box.hit(ray, t_min, t_max) {
+impl Hittable for Bvh {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
+        if self.nodes.is_empty() {
             return false;
         }
 
-        let hit_left = self.left.hit(ray, t_min, t_max, rec);
-        let right_t_max = if hit_left { rec.t } else { t_max };
-        let hit_right = self.right.hit(ray, t_min, right_t_max, rec);
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        // Second children still to visit.
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut index = 0;
+
+        loop {
+            let node = &self.nodes[index];
+            if node.bounding_box.hit(ray, t_min, closest_so_far) {
+                if node.primitive_count > 0 {
+                    let first = node.offset as usize;
+                    let last = first + node.primitive_count as usize;
+                    for object in &self.primitives[first..last] {
+                        if object.hit(ray, t_min, closest_so_far, rec, rng) {
+                            hit_anything = true;
+                            closest_so_far = rec.t;
+                        }
+                    }
+                } else {
+                    // Visit the child on the side the ray comes from first, so
+                    // that its hits can cull the other.
+                    let (near, far) = if ray.direction[node.axis as usize] < 0.0 {
+                        (node.offset as usize, index + 1)
+                    } else {
+                        (index + 1, node.offset as usize)
+                    };
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    index = near;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            index = stack[stack_size];
+        }
 
-        hit_left || hit_right
+        hit_anything
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-        Some(self.bounding_box)
+        self.nodes.first().map(|node| node.bounding_box)
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::opaque("BVH"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{Rng, SeedableRng};
+
+    use crate::{
+        material::{Lambertian, MaterialPtr},
+        sphere::Sphere,
+        vec3::{Color, Vec3},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_bvh_matches_list() {
+        let material: MaterialPtr =
+            Arc::new(Lambertian::new_from_color(&Color::new(0.5, 0.5, 0.5)));
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let mut list = HittableList::new();
+        for _ in 0..200 {
+            let center = Point3::new(
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(-10.0..10.0),
+            );
+            list.add(Arc::new(Sphere::new(
+                center,
+                rng.gen_range(0.1..1.0),
+                &material,
+            )));
+        }
+        let bvh = Bvh::from_hittable_list(&list, 0.0, 1.0);
+
+        let stats = bvh.stats();
+        assert_eq!(stats.primitive_count, 200);
+        assert!(stats.average_primitives_per_leaf() <= MAX_PRIMITIVES_PER_LEAF as f64);
+
+        for _ in 0..1000 {
+            let origin = Point3::new(rng.gen_range(-20.0..20.0), 20.0, rng.gen_range(-20.0..20.0));
+            let direction = Vec3::new(rng.gen_range(-1.0..1.0), -1.0, rng.gen_range(-1.0..1.0));
+            let ray = Ray::new_at_t0(origin, direction);
+
+            let mut list_rec = HitRecord::empty();
+            let mut bvh_rec = HitRecord::empty();
+            let list_hit = list.hit(&ray, 0.001, f64::INFINITY, &mut list_rec, &mut rng);
+            let bvh_hit = bvh.hit(&ray, 0.001, f64::INFINITY, &mut bvh_rec, &mut rng);
+
+            assert_eq!(list_hit, bvh_hit);
+            if list_hit {
+                assert_eq!(list_rec.t, bvh_rec.t);
+            }
+        }
     }
 }
@@ -1,6 +1,10 @@
 use rand::Rng;
 
-use crate::{ray, vec3};
+use crate::{matrix::Matrix4, ray, vec3};
+
+/// Closest the camera may orbit to looking straight along its up direction,
+/// where the view would flip over.
+const MIN_POLAR_ANGLE: f64 = 1.0;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -14,6 +18,15 @@ pub struct Camera {
     lens_radius: f64,
     shutter_open_time: f64,
     shutter_close_time: f64,
+    /// The parameters the camera was built from, to rebuild it with some of
+    /// them changed.
+    look_from: vec3::Point3,
+    look_at: vec3::Point3,
+    v_up: vec3::Vec3,
+    vfov_degrees: f64,
+    aspect_ratio: f64,
+    aperture: f64,
+    focus_dist: f64,
 }
 
 impl Camera {
@@ -56,18 +69,95 @@ impl Camera {
             lens_radius,
             shutter_open_time,
             shutter_close_time,
+            look_from,
+            look_at,
+            v_up,
+            vfov_degrees,
+            aspect_ratio,
+            aperture,
+            focus_dist,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> ray::Ray {
-        let rd = self.lens_radius * vec3::random_in_unit_disk();
+    /// This camera with its shutter open from `open` to `close`.
+    pub fn with_shutter(&self, open: f64, close: f64) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.v_up,
+            self.vfov_degrees,
+            self.aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            open,
+            close,
+        )
+    }
+
+    /// This camera with its viewport stretched to `aspect_ratio`.
+    pub fn with_aspect_ratio(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.v_up,
+            self.vfov_degrees,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            self.shutter_open_time,
+            self.shutter_close_time,
+        )
+    }
+
+    /// This camera placed as in `keyframe`, keeping its up direction, aspect
+    /// ratio and shutter.
+    pub fn with_keyframe(&self, keyframe: &CameraKeyframe) -> Camera {
+        Camera::new(
+            keyframe.look_from,
+            keyframe.look_at,
+            self.v_up,
+            keyframe.vfov_degrees,
+            self.aspect_ratio,
+            keyframe.aperture,
+            keyframe.focus_dist,
+            self.shutter_open_time,
+            self.shutter_close_time,
+        )
+    }
+
+    /// The placement of this camera, as a keyframe at `time`.
+    pub fn keyframe(&self, time: f64) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            look_from: self.look_from,
+            look_at: self.look_at,
+            vfov_degrees: self.vfov_degrees,
+            aperture: self.aperture,
+            focus_dist: self.focus_dist,
+        }
+    }
+
+    pub fn v_up(&self) -> &vec3::Vec3 {
+        &self.v_up
+    }
+
+    pub fn shutter_open_time(&self) -> f64 {
+        self.shutter_open_time
+    }
+
+    pub fn shutter_close_time(&self) -> f64 {
+        self.shutter_close_time
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, rng: &mut impl Rng) -> ray::Ray {
+        let rd = self.lens_radius * vec3::random_in_unit_disk(rng);
         let offset = self.u * rd.x() + self.v * rd.y();
 
         let direction =
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset;
 
-        let mut rng = rand::thread_rng();
-        let time = rng.gen_range(self.shutter_open_time..self.shutter_close_time);
+        let time = self.shutter_open_time
+            + rng.gen::<f64>() * (self.shutter_close_time - self.shutter_open_time);
         ray::Ray::new(self.origin + offset, direction, time)
     }
 
@@ -75,3 +165,137 @@ impl Camera {
         &self.w
     }
 }
+
+/// Placement of a camera at one point in time.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub look_from: vec3::Point3,
+    pub look_at: vec3::Point3,
+    pub vfov_degrees: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+}
+
+impl CameraKeyframe {
+    /// Swing `look_from` around `look_at` by `yaw` degrees about `up` and
+    /// `pitch` degrees towards it, stopping short of looking straight up or
+    /// down.
+    pub fn orbit(&self, up: &vec3::Vec3, yaw: f64, pitch: f64) -> CameraKeyframe {
+        let offset = self.look_from - self.look_at;
+        let polar = vec3::unit_vector(&offset)
+            .dot(&vec3::unit_vector(up))
+            .clamp(-1.0, 1.0)
+            .acos()
+            .to_degrees();
+        let new_polar = (polar - pitch).clamp(MIN_POLAR_ANGLE, 180.0 - MIN_POLAR_ANGLE);
+        let rotation =
+            Matrix4::rotation(up, yaw) * Matrix4::rotation(&offset.cross(up), polar - new_polar);
+        CameraKeyframe {
+            look_from: self.look_at + rotation.transform_vector(&offset),
+            ..*self
+        }
+    }
+
+    /// Slide the camera and the point it looks at across the view, by `right`
+    /// and `up` times the height of the view at `look_at`.
+    pub fn pan(&self, v_up: &vec3::Vec3, right: f64, up: f64) -> CameraKeyframe {
+        let offset = self.look_from - self.look_at;
+        let view_height = 2.0 * offset.length() * (self.vfov_degrees.to_radians() / 2.0).tan();
+        let w = vec3::unit_vector(&offset);
+        let u = vec3::unit_vector(&v_up.cross(&w));
+        let v = w.cross(&u);
+        let shift = view_height * (right * u + up * v);
+        CameraKeyframe {
+            look_from: self.look_from + shift,
+            look_at: self.look_at + shift,
+            ..*self
+        }
+    }
+
+    /// Move the camera towards `look_at`, scaling its distance by `factor`.
+    pub fn zoom(&self, factor: f64) -> CameraKeyframe {
+        CameraKeyframe {
+            look_from: self.look_at + factor * (self.look_from - self.look_at),
+            ..*self
+        }
+    }
+
+    fn interpolate(&self, other: &CameraKeyframe, t: f64) -> CameraKeyframe {
+        let lerp = |a: f64, b: f64| a + t * (b - a);
+        CameraKeyframe {
+            time: lerp(self.time, other.time),
+            look_from: self.look_from + t * (other.look_from - self.look_from),
+            look_at: self.look_at + t * (other.look_at - self.look_at),
+            vfov_degrees: lerp(self.vfov_degrees, other.vfov_degrees),
+            aperture: lerp(self.aperture, other.aperture),
+            focus_dist: lerp(self.focus_dist, other.focus_dist),
+        }
+    }
+}
+
+/// Keyframed camera motion, interpolated linearly and holding the first and
+/// last placements before and after the keyframes.
+#[derive(Debug, Clone)]
+pub struct CameraAnimation {
+    keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraAnimation {
+    /// Panics if `keyframes` is empty.
+    pub fn new(mut keyframes: Vec<CameraKeyframe>) -> CameraAnimation {
+        assert!(!keyframes.is_empty(), "CameraAnimation needs a keyframe");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        CameraAnimation { keyframes }
+    }
+
+    /// The placement at `time`.
+    pub fn at(&self, time: f64) -> CameraKeyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+
+        let (start, end) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        start.interpolate(end, (time - start.time) / (end.time - start.time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn test_navigation() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let start = CameraKeyframe {
+            time: 0.0,
+            look_from: Point3::new(0.0, 0.0, 10.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vfov_degrees: 90.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+        };
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-9;
+
+        let orbited = start.orbit(&up, 90.0, 0.0);
+        assert!(close(orbited.look_from, Point3::new(10.0, 0.0, 0.0)));
+        let orbited = start.orbit(&up, 0.0, 45.0);
+        let height = 10.0 * 45f64.to_radians().sin();
+        assert!(close(orbited.look_from, Point3::new(0.0, height, height)));
+        // Stops just short of the pole.
+        let orbited = start.orbit(&up, 0.0, 120.0);
+        assert!(orbited.look_from.y() < 10.0 && orbited.look_from.z() > 0.0);
+        assert!((orbited.look_from.length() - 10.0).abs() < 1e-9);
+
+        let panned = start.pan(&up, 0.5, -0.25);
+        assert!(close(panned.look_at, Point3::new(10.0, -5.0, 0.0)));
+        assert!(close(panned.look_from - panned.look_at, start.look_from));
+
+        assert!(close(start.zoom(0.5).look_from, Point3::new(0.0, 0.0, 5.0)));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    fmt, fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use image::{Luma, Rgb, Rgb32FImage};
+
+use crate::{
+    render::{RenderOutput, SampleCounts},
+    scenes::SceneConfig,
+};
+
+/// How often a render saves a checkpoint unless told otherwise.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Identifies the checkpoint file format.
+const MAGIC: &[u8; 8] = b"RTCKPT01";
+
+/// Bytes of the header: magic, scene hash, width and height.
+const HEADER_LEN: usize = 8 + 8 + 4 + 4;
+
+/// Bytes stored per pixel: three `f32` color channels and a `u32` count.
+const PIXEL_LEN: usize = 4 * 4;
+
+/// The state of an unfinished render, from which it can be resumed.
+///
+/// As every sample's random numbers depend only on the seed, the pixel and
+/// the sample's index, the samples taken so far are all it takes to carry on
+/// where the render left off.
+#[derive(Clone)]
+pub struct Checkpoint {
+    /// [`scene_hash`] of the scene being rendered.
+    pub scene_hash: u64,
+    pub render: RenderOutput,
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// The file could not be read or written.
+    Io(PathBuf, io::Error),
+    /// The file is not a checkpoint, or is truncated.
+    Corrupt(PathBuf),
+    /// The checkpoint is of another scene, or of the same scene rendered
+    /// with different settings.
+    SceneMismatch(PathBuf),
+    /// The checkpoint is of an image of another size.
+    ResolutionMismatch {
+        path: PathBuf,
+        checkpoint: (u32, u32),
+        scene: (u32, u32),
+    },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            CheckpointError::Corrupt(path) => {
+                write!(f, "{}: not a valid checkpoint file", path.display())
+            }
+            CheckpointError::SceneMismatch(path) => write!(
+                f,
+                "{}: the checkpoint is of a different scene or render settings",
+                path.display()
+            ),
+            CheckpointError::ResolutionMismatch {
+                path,
+                checkpoint: (checkpoint_width, checkpoint_height),
+                scene: (scene_width, scene_height),
+            } => write!(
+                f,
+                "{}: the checkpoint is {checkpoint_width} x {checkpoint_height} pixels but the \
+                 image is {scene_width} x {scene_height}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl Checkpoint {
+    /// Save to `path`, replacing it only once the new checkpoint is completely
+    /// written, so that being killed while saving keeps the previous one.
+    pub fn save(&self, path: &Path) -> Result<(), CheckpointError> {
+        let io_error = |e| CheckpointError::Io(path.to_path_buf(), e);
+        let mut partial_name = path.as_os_str().to_owned();
+        partial_name.push(".partial");
+        let partial_path = PathBuf::from(partial_name);
+
+        let file = fs::File::create(&partial_path).map_err(io_error)?;
+        let mut writer = BufWriter::new(file);
+        let (width, height) = self.render.image.dimensions();
+        let write = || -> io::Result<()> {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&self.scene_hash.to_le_bytes())?;
+            writer.write_all(&width.to_le_bytes())?;
+            writer.write_all(&height.to_le_bytes())?;
+            let counts = self.render.sample_counts.pixels();
+            for (color, Luma([count])) in self.render.image.pixels().zip(counts) {
+                for channel in color.0 {
+                    writer.write_all(&channel.to_le_bytes())?;
+                }
+                writer.write_all(&count.to_le_bytes())?;
+            }
+            writer.into_inner()?.sync_all()
+        };
+        write().map_err(io_error)?;
+        fs::rename(&partial_path, path).map_err(io_error)
+    }
+
+    pub fn load(path: &Path) -> Result<Checkpoint, CheckpointError> {
+        let bytes = fs::read(path).map_err(|e| CheckpointError::Io(path.to_path_buf(), e))?;
+        let corrupt = || CheckpointError::Corrupt(path.to_path_buf());
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(corrupt());
+        }
+
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let scene_hash = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let (width, height) = (u32_at(16), u32_at(20));
+        let pixel_count = width as usize * height as usize;
+        if bytes.len() != HEADER_LEN + pixel_count * PIXEL_LEN {
+            return Err(corrupt());
+        }
+
+        let mut image = Rgb32FImage::new(width, height);
+        let mut sample_counts = SampleCounts::new(width, height);
+        for (i, (color, count)) in image
+            .pixels_mut()
+            .zip(sample_counts.pixels_mut())
+            .enumerate()
+        {
+            let start = HEADER_LEN + i * PIXEL_LEN;
+            let channel = |c: usize| f32::from_bits(u32_at(start + 4 * c));
+            *color = Rgb([channel(0), channel(1), channel(2)]);
+            *count = Luma([u32_at(start + 12)]);
+        }
+
+        Ok(Checkpoint {
+            scene_hash,
+            render: RenderOutput {
+                image,
+                sample_counts,
+            },
+        })
+    }
+
+    /// Check that the render can be resumed from this checkpoint, which was
+    /// loaded from `path`.
+    pub fn check_matches(
+        &self,
+        path: &Path,
+        scene_hash: u64,
+        scene: &SceneConfig,
+    ) -> Result<(), CheckpointError> {
+        let checkpoint_size = self.render.image.dimensions();
+        let scene_size = scene.image_size();
+        if checkpoint_size != scene_size {
+            return Err(CheckpointError::ResolutionMismatch {
+                path: path.to_path_buf(),
+                checkpoint: checkpoint_size,
+                scene: scene_size,
+            });
+        }
+        if self.scene_hash != scene_hash {
+            return Err(CheckpointError::SceneMismatch(path.to_path_buf()));
+        }
+        Ok(())
+    }
+}
+
+/// Hash identifying what a render's samples depend on: the scene, given by
+/// `scene_source` such as the contents of its file, and the settings that
+/// change every sample. The samples per pixel are left out, so that a
+/// finished render can be resumed to add more.
+pub fn scene_hash(scene_source: &[u8], scene: &SceneConfig) -> u64 {
+    // FNV-1a, which unlike the standard library's hasher is guaranteed to
+    // stay the same between builds.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut add = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    };
+    add(scene_source);
+    add(&scene.seed.to_le_bytes());
+    add(&scene.max_depth.to_le_bytes());
+    add(&scene.camera.shutter_open_time().to_le_bytes());
+    add(&scene.camera.shutter_close_time().to_le_bytes());
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes::Scene;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut render = RenderOutput {
+            image: Rgb32FImage::new(3, 2),
+            sample_counts: SampleCounts::new(3, 2),
+        };
+        render.image.put_pixel(2, 1, Rgb([0.25, 1.5, f32::MAX]));
+        render.sample_counts.put_pixel(2, 1, Luma([7]));
+        let checkpoint = Checkpoint {
+            scene_hash: 42,
+            render,
+        };
+
+        let path = std::env::temp_dir().join(format!("checkpoint_{}.bin", std::process::id()));
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path);
+        fs::write(&path, b"RTCKPT01 truncated").unwrap();
+        let truncated = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.scene_hash, 42);
+        assert_eq!(loaded.render.image, checkpoint.render.image);
+        assert_eq!(loaded.render.sample_counts, checkpoint.render.sample_counts);
+        assert!(matches!(truncated, Err(CheckpointError::Corrupt(_))));
+
+        let mut scene = SceneConfig::get_scene(&Scene::CornellBox);
+        scene.image_width = 3;
+        scene.set_aspect_ratio(1.5);
+        let hash = scene_hash(b"cornell", &scene);
+        assert!(loaded.check_matches(&path, 42, &scene).is_ok());
+        assert!(matches!(
+            loaded.check_matches(&path, hash, &scene),
+            Err(CheckpointError::SceneMismatch(_))
+        ));
+        scene.seed += 1;
+        assert_ne!(scene_hash(b"cornell", &scene), hash);
+        scene.image_width = 4;
+        assert!(matches!(
+            loaded.check_matches(&path, 42, &scene),
+            Err(CheckpointError::ResolutionMismatch { .. })
+        ));
+    }
+}
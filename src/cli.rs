@@ -1,8 +1,15 @@
-use std::path::PathBuf;
+use std::{fs, io, ops::Range, path::PathBuf, time::Duration};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use crate::scenes::Scene;
+use rust_raytracing::{
+    render::AdaptiveSampling,
+    scene_file::{self, SceneFileError},
+    scenes::{Scene, SceneConfig},
+    sequence::{self, Sequence},
+    tiles::TileOrder,
+    tone_map::{ToneMapOperator, ToneMapping},
+};
 
 #[derive(Debug, Parser)]
 #[command(about, long_about = None)]
@@ -15,7 +22,226 @@ pub struct Cli {
     #[arg(short, long, value_name = "FILE", requires = "no_gui")]
     pub output: Option<PathBuf>,
 
-    /// Scene to render
-    #[arg(short, long, requires = "no_gui")]
-    pub scene: Option<Scene>,
+    /// Scene to render: a built-in scene name or a path to a TOML scene file
+    #[arg(short, long, requires = "no_gui", value_parser = parse_scene_source)]
+    pub scene: Option<SceneSource>,
+
+    /// Sample each pixel only until the relative error of its mean falls
+    /// below this threshold, up to the scene's samples per pixel
+    #[arg(long, value_name = "THRESHOLD", requires = "no_gui", value_parser = parse_positive)]
+    pub adaptive: Option<f64>,
+
+    /// Samples taken for every pixel before adaptive sampling may stop
+    #[arg(long, default_value_t = 16, requires = "adaptive")]
+    pub min_samples: u32,
+
+    /// Also save an image of the number of samples taken per pixel
+    #[arg(long, value_name = "FILE", requires = "no_gui")]
+    pub heatmap: Option<PathBuf>,
+
+    /// Render an image sequence of these frames, e.g. 0..120, saving each to
+    /// the output file name with its %04d replaced by the frame number
+    #[arg(long, value_name = "RANGE", requires = "no_gui", value_parser = sequence::parse_frame_range)]
+    pub frames: Option<Range<u32>>,
+
+    /// Frames per second of scene time
+    #[arg(long, default_value_t = 24.0, requires = "frames", value_parser = parse_positive)]
+    pub fps: f64,
+
+    /// Fraction of each frame the shutter stays open
+    #[arg(long, default_value_t = 0.5, requires = "frames")]
+    pub shutter: f64,
+
+    /// Skip frames whose output file already exists
+    #[arg(long, requires = "frames")]
+    pub skip_existing: bool,
+
+    /// Image width in pixels, overriding the scene's
+    #[arg(long, requires = "no_gui", value_parser = clap::value_parser!(u32).range(2..))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels, changing the scene's aspect ratio to fit
+    #[arg(long, requires = "no_gui", conflicts_with = "aspect", value_parser = clap::value_parser!(u32).range(2..))]
+    pub height: Option<u32>,
+
+    /// Aspect ratio of the image, overriding the scene's
+    #[arg(long, requires = "no_gui", value_parser = parse_positive)]
+    pub aspect: Option<f64>,
+
+    /// Samples per pixel, overriding the scene's
+    #[arg(long, requires = "no_gui", value_parser = clap::value_parser!(u32).range(1..))]
+    pub spp: Option<u32>,
+
+    /// Maximum number of ray bounces, overriding the scene's
+    #[arg(long, requires = "no_gui")]
+    pub max_depth: Option<u32>,
+
+    /// Number of render threads [default: all CPUs but one]
+    #[arg(long, requires = "no_gui", value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Order in which the image's tiles are rendered
+    #[arg(long, value_enum, default_value_t = TileOrder::Scanline, requires = "no_gui")]
+    pub tile_order: TileOrder,
+
+    /// Seed for the random sampling, overriding the scene's. Renders with the
+    /// same seed are identical
+    #[arg(long, requires = "no_gui")]
+    pub seed: Option<u64>,
+
+    /// Seconds after which the remaining pixels take a single sample each
+    #[arg(long, value_name = "SECONDS", requires = "no_gui", value_parser = parse_positive)]
+    pub time_limit: Option<f64>,
+
+    /// Periodically save the render's progress to this file, to resume from
+    /// with --resume if it is interrupted
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "no_gui",
+        conflicts_with = "frames"
+    )]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints
+    #[arg(long, value_name = "SECONDS", default_value_t = 60.0, value_parser = parse_positive)]
+    pub checkpoint_interval: f64,
+
+    /// Continue the render saved in this checkpoint, adding the samples it
+    /// lacks, and keep checkpointing to it unless --checkpoint is given
+    #[arg(
+        long,
+        value_name = "CHECKPOINT",
+        requires = "no_gui",
+        conflicts_with = "frames"
+    )]
+    pub resume: Option<PathBuf>,
+
+    /// Tone mapping operator used for 8-bit output
+    #[arg(long, value_enum, default_value_t = ToneMapOperator::Clamp)]
+    pub tone_map: ToneMapOperator,
+
+    /// Exposure adjustment in stops
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub exposure: f32,
+
+    /// Radiance mapped to white by the extended-reinhard operator
+    #[arg(long, default_value_t = 4.0)]
+    pub white_point: f32,
+}
+
+impl Cli {
+    pub fn adaptive_sampling(&self) -> Option<AdaptiveSampling> {
+        self.adaptive
+            .map(|threshold| AdaptiveSampling::new(self.min_samples, threshold))
+    }
+
+    pub fn sequence(&self) -> Option<Sequence> {
+        self.frames.clone().map(|frames| Sequence {
+            frames,
+            fps: self.fps,
+            shutter: self.shutter,
+            skip_existing: self.skip_existing,
+        })
+    }
+
+    /// Apply the render settings given on the command line to `scene`,
+    /// failing if they leave the image less than 2 pixels high.
+    pub fn override_settings(&self, scene: &mut SceneConfig) -> Result<(), String> {
+        if let Some(width) = self.width {
+            scene.image_width = width;
+        }
+        if let Some(height) = self.height {
+            scene.set_aspect_ratio(scene.image_width as f64 / height as f64);
+        }
+        if let Some(aspect) = self.aspect {
+            scene.set_aspect_ratio(aspect);
+        }
+        let (width, height) = scene.image_size();
+        if height < 2 {
+            return Err(format!(
+                "the image would be {width} x {height} pixels, but must be at least 2 pixels high"
+            ));
+        }
+        if let Some(spp) = self.spp {
+            scene.samples_per_pixel = spp;
+        }
+        if let Some(max_depth) = self.max_depth {
+            scene.max_depth = max_depth;
+        }
+        if let Some(seed) = self.seed {
+            scene.seed = seed;
+        }
+        if let Some(seconds) = self.time_limit {
+            scene.time_limit = Some(Duration::from_secs_f64(seconds));
+        }
+        if let Some(adaptive_sampling) = self.adaptive_sampling() {
+            scene.adaptive_sampling = Some(adaptive_sampling);
+        }
+        Ok(())
+    }
+
+    /// File to save checkpoints to, if any.
+    pub fn checkpoint_file(&self) -> Option<PathBuf> {
+        self.checkpoint.clone().or_else(|| self.resume.clone())
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        ToneMapping::new(self.tone_map, self.exposure, self.white_point)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SceneSource {
+    BuiltIn(Scene),
+    File(PathBuf),
+}
+
+impl SceneSource {
+    /// Load the scene to render at `times` besides its own shutter times,
+    /// such as those of the frames of a sequence.
+    pub fn load(&self, times: &[f64]) -> Result<SceneConfig, SceneFileError> {
+        match self {
+            SceneSource::BuiltIn(scene) => Ok(SceneConfig::get_scene(scene)),
+            SceneSource::File(path) => scene_file::load_scene_file_spanning(path, times),
+        }
+    }
+
+    /// Bytes identifying the scene for checkpoints: the name of a built-in
+    /// scene or the contents of a scene file.
+    pub fn fingerprint(&self) -> io::Result<Vec<u8>> {
+        match self {
+            SceneSource::BuiltIn(scene) => Ok(format!("{scene:?}").into_bytes()),
+            SceneSource::File(path) => fs::read(path),
+        }
+    }
+}
+
+fn parse_positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_scene_source(value: &str) -> Result<SceneSource, String> {
+    if let Ok(scene) = Scene::from_str(value, true) {
+        return Ok(SceneSource::BuiltIn(scene));
+    }
+
+    let path = PathBuf::from(value);
+    if path.is_file() {
+        return Ok(SceneSource::File(path));
+    }
+
+    let scenes: Vec<String> = Scene::value_variants()
+        .iter()
+        .filter_map(|scene| scene.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    Err(format!(
+        "not a built-in scene or an existing scene file (built-in scenes: {})",
+        scenes.join(", ")
+    ))
 }
@@ -4,7 +4,9 @@ use rand::Rng;
 
 use crate::{
     hittable::{HitRecord, Hittable, HittablePtr},
+    inspector::{self, Node, Parameter, Part},
     material::{Isotropic, MaterialPtr},
+    rng::RenderRng,
     texture::TexturePtr,
     vec3::{Color, Vec3},
 };
@@ -31,6 +33,18 @@ impl ConstantMedium {
             negative_inverse_density: (-1.0 / density),
         }
     }
+
+    pub fn new_from_phase_function(
+        boundary: &HittablePtr,
+        density: f64,
+        phase_function: &MaterialPtr,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary: Arc::clone(boundary),
+            phase_function: Arc::clone(phase_function),
+            negative_inverse_density: (-1.0 / density),
+        }
+    }
 }
 
 impl Hittable for ConstantMedium {
@@ -44,10 +58,10 @@ impl Hittable for ConstantMedium {
         t_min: f64,
         t_max: f64,
         rec: &mut crate::hittable::HitRecord,
+        rng: &mut RenderRng,
     ) -> bool {
         // Print occasional sample when debugging. To enable, set enable_debug true.
         let enable_debug: bool = false;
-        let mut rng = rand::thread_rng();
         let debugging = enable_debug && (rng.gen::<f64>() < 0.00001);
 
         let mut rec1 = HitRecord::empty();
@@ -55,14 +69,14 @@ impl Hittable for ConstantMedium {
 
         if !self
             .boundary
-            .hit(ray, -f64::INFINITY, f64::INFINITY, &mut rec1)
+            .hit(ray, -f64::INFINITY, f64::INFINITY, &mut rec1, rng)
         {
             return false;
         }
 
         if !self
             .boundary
-            .hit(ray, rec1.t + 0.0001, f64::INFINITY, &mut rec2)
+            .hit(ray, rec1.t + 0.0001, f64::INFINITY, &mut rec2, rng)
         {
             return false;
         }
@@ -109,4 +123,26 @@ impl Hittable for ConstantMedium {
 
         true
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Constant medium",
+            vec![Parameter::number(
+                "Density",
+                -1.0 / self.negative_inverse_density,
+                0.0,
+            )],
+            vec![
+                inspector::object("Boundary", &self.boundary),
+                inspector::material("Phase function", &self.phase_function),
+            ],
+            |values, parts| {
+                Part::Object(Arc::new(ConstantMedium::new_from_phase_function(
+                    &parts[0].object(),
+                    values[0].number(),
+                    &parts[1].material(),
+                )))
+            },
+        ))
+    }
 }
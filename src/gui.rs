@@ -1,17 +1,43 @@
 use std::time::{Duration, Instant};
 
-use crate::{
+use clap::ValueEnum;
+
+use rust_raytracing::{
+    camera::CameraKeyframe,
+    inspector::{Node, Parameter, SceneInspector, Value},
+    output,
     render::ThreadedRenderer,
     scenes::{Scene, SceneConfig},
+    tiles::TileOrder,
+    tone_map::{ToneMapOperator, ToneMapping},
+    vec3::{Color, Vec3},
 };
 
+/// Degrees the camera orbits by per point dragged.
+const ORBIT_DEGREES_PER_POINT: f64 = 0.3;
+
+/// Scroll distance in points that halves or doubles the camera's distance.
+const ZOOM_POINTS_PER_OCTAVE: f64 = 200.0;
+
 pub struct Gui {
     renderer: ThreadedRenderer,
     texture: Option<egui::TextureHandle>,
     render_start_time: Option<Instant>,
+    /// When the render in progress was paused, to leave the pause out of the
+    /// render time.
+    pause_start_time: Option<Instant>,
     render_time: Option<Duration>,
     scene: Scene,
+    /// The scene last rendered, rendered again as its camera is moved.
+    scene_config: Option<SceneConfig>,
+    /// Objects of `scene_config`, for editing them.
+    inspector: Option<SceneInspector>,
+    /// Whether the Render button renders in passes. Moving the camera always
+    /// does, to show a preview at once.
+    progressive: bool,
     num_cpus: usize,
+    /// Set when the display pixels changed without new render progress.
+    texture_outdated: bool,
 }
 
 impl Default for Gui {
@@ -20,19 +46,26 @@ impl Default for Gui {
             renderer: Default::default(),
             texture: None,
             render_start_time: None,
+            pause_start_time: None,
             render_time: None,
             scene: Scene::Random,
+            scene_config: None,
+            inspector: None,
+            progressive: true,
             num_cpus: 1,
+            texture_outdated: false,
         }
     }
 }
 
 impl Gui {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self {
+    pub fn new(_cc: &eframe::CreationContext<'_>, tone_mapping: ToneMapping) -> Self {
+        let mut gui = Self {
             num_cpus: num_cpus::get(),
             ..Default::default()
-        }
+        };
+        gui.renderer.set_tone_mapping(tone_mapping);
+        gui
     }
 }
 
@@ -64,11 +97,18 @@ impl eframe::App for Gui {
 
         self.try_load_texture(ctx);
 
+        egui::SidePanel::left("inspector_panel").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| self.inspector_panel(ui));
+        });
+
         egui::CentralPanel::default()
             .frame(frame)
-            .show(ctx, |ui| match self.texture.as_ref() {
+            .show(ctx, |ui| match self.texture.clone() {
                 Some(texture) => {
-                    ui.image(texture);
+                    let image = egui::Image::new(&texture).sense(egui::Sense::drag());
+                    let response = ui.add(image);
+                    self.paint_tiles_in_flight(ui, response.rect);
+                    self.navigate(ui, &response);
                 }
                 None => {
                     ui.spinner();
@@ -78,7 +118,12 @@ impl eframe::App for Gui {
         egui::SidePanel::right("side_panel").show(ctx, |ui| {
             ui.add_enabled_ui(!self.renderer.is_render_in_progress(), |ui| {
                 self.side_panel(ui);
-            })
+            });
+            self.render_control_panel(ui);
+            ui.separator();
+            self.camera_panel(ui);
+            ui.separator();
+            self.tone_mapping_panel(ui);
         });
     }
 }
@@ -86,13 +131,103 @@ impl eframe::App for Gui {
 impl Gui {
     fn start_render(&mut self) {
         let scene = SceneConfig::get_scene(&self.scene);
+        self.inspector = Some(SceneInspector::new(&scene));
+        self.scene_config = Some(scene);
+        self.render_scene(self.progressive);
+    }
+
+    /// Render `scene_config` from scratch, stopping any render in progress.
+    fn render_scene(&mut self, progressive: bool) {
+        let Some(scene) = self.scene_config.clone() else {
+            return;
+        };
+        self.renderer.cancel();
+        self.renderer.progressive = progressive;
         self.renderer.start_render(scene);
         self.render_start_time = Some(Instant::now());
+        self.pause_start_time = None;
+        self.render_time = None;
+    }
+
+    /// Place the camera as in `camera` and preview the scene from there.
+    fn move_camera(&mut self, camera: &CameraKeyframe) {
+        let Some(scene) = self.scene_config.as_mut() else {
+            return;
+        };
+        // Looking at the camera's own position gives no view direction.
+        if (camera.look_from - camera.look_at).length() < 1e-9 {
+            return;
+        }
+        scene.camera = scene.camera.with_keyframe(camera);
+        self.render_scene(true);
+    }
+
+    /// Orbit the camera by dragging over the image, pan it by dragging with
+    /// the secondary or middle button or with shift held, and zoom by
+    /// scrolling.
+    fn navigate(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        let Some(scene) = &self.scene_config else {
+            return;
+        };
+        let up = *scene.camera.v_up();
+        let mut camera = scene.camera.keyframe(0.0);
+        let mut moved = false;
+
+        let delta = response.drag_delta();
+        let (dx, dy) = (delta.x as f64, delta.y as f64);
+        let shift = ui.input(|input| input.modifiers.shift);
+        if delta != egui::Vec2::ZERO {
+            if response.dragged_by(egui::PointerButton::Primary) && !shift {
+                camera = camera.orbit(
+                    &up,
+                    -dx * ORBIT_DEGREES_PER_POINT,
+                    dy * ORBIT_DEGREES_PER_POINT,
+                );
+                moved = true;
+            } else if response.dragged() {
+                let height = response.rect.height().max(1.0) as f64;
+                camera = camera.pan(&up, -dx / height, dy / height);
+                moved = true;
+            }
+        }
+
+        let scroll = ui.input(|input| input.smooth_scroll_delta.y) as f64;
+        if response.hovered() && scroll != 0.0 {
+            camera = camera.zoom(0.5f64.powf(scroll / ZOOM_POINTS_PER_OCTAVE));
+            moved = true;
+        }
+
+        if moved {
+            self.move_camera(&camera);
+        }
+    }
+
+    fn pause_render(&mut self) {
+        self.renderer.pause();
+        self.pause_start_time = Some(Instant::now());
+    }
+
+    fn resume_render(&mut self) {
+        self.renderer.resume();
+        if let (Some(start), Some(paused)) = (
+            self.render_start_time.as_mut(),
+            self.pause_start_time.take(),
+        ) {
+            *start += paused.elapsed();
+        }
+    }
+
+    fn cancel_render(&mut self) {
+        self.renderer.cancel();
+        self.render_start_time = None;
+        self.pause_start_time = None;
+        self.render_time = None;
+        self.texture_outdated = true;
     }
 
     fn try_load_texture(&mut self, ctx: &egui::Context) {
         let progress = self.renderer.check_progress();
-        if progress {
+        if progress || std::mem::take(&mut self.texture_outdated) {
             let size = self.renderer.get_image_size();
             let pixels = self.renderer.get_pixels();
             let image = egui::ColorImage::from_rgba_unmultiplied(size, pixels);
@@ -100,6 +235,20 @@ impl Gui {
         }
     }
 
+    /// Outline the tiles the render threads are working on over the image
+    /// shown in `rect`.
+    fn paint_tiles_in_flight(&self, ui: &egui::Ui, rect: egui::Rect) {
+        let [width, _] = self.renderer.get_image_size();
+        let scale = rect.width() / width.max(1) as f32;
+        let stroke = egui::Stroke::new(1.0, egui::Color32::YELLOW);
+        for tile in self.renderer.tiles_in_flight() {
+            let min = rect.min + scale * egui::vec2(tile.x as f32, tile.y as f32);
+            let size = scale * egui::vec2(tile.width as f32, tile.height as f32);
+            ui.painter()
+                .rect_stroke(egui::Rect::from_min_size(min, size), 0.0, stroke);
+        }
+    }
+
     fn side_panel(&mut self, ui: &mut egui::Ui) {
         ui.heading("Controls");
 
@@ -114,43 +263,283 @@ impl Gui {
                 ui.selectable_value(&mut self.scene, Scene::CornellBox, "CornellBox");
                 ui.selectable_value(&mut self.scene, Scene::CornellSmoke, "CornellSmoke");
                 ui.selectable_value(&mut self.scene, Scene::FinalScene, "FinalScene");
+                ui.selectable_value(&mut self.scene, Scene::Mesh, "Mesh");
             });
 
         ui.add(
             egui::Slider::new(&mut self.renderer.threads_to_use, 1..=self.num_cpus).text("Threads"),
         );
 
+        egui::ComboBox::from_label("Tile order")
+            .selected_text(format!("{:?}", self.renderer.tile_order))
+            .show_ui(ui, |ui| {
+                for order in TileOrder::value_variants() {
+                    ui.selectable_value(
+                        &mut self.renderer.tile_order,
+                        *order,
+                        format!("{order:?}"),
+                    );
+                }
+            });
+
+        ui.checkbox(&mut self.progressive, "Progressive")
+            .on_hover_text("Refine the whole image in passes of doubling samples per pixel");
+
         if ui.button("Render").clicked() {
             self.start_render();
         }
 
         let [width, height] = self.renderer.get_image_size();
         ui.label(format!("Image size {} x {}", width, height));
+    }
 
+    /// Progress of the render, with buttons to pause or cancel it while in
+    /// progress and to save it once done.
+    fn render_control_panel(&mut self, ui: &mut egui::Ui) {
         if self.renderer.is_render_in_progress() {
-            ui.spinner();
+            ui.horizontal(|ui| {
+                if self.renderer.is_paused() {
+                    if ui.button("Resume").clicked() {
+                        self.resume_render();
+                    }
+                    ui.label("Paused");
+                } else {
+                    if ui.button("Pause").clicked() {
+                        self.pause_render();
+                    }
+                    ui.spinner();
+                }
+                if ui.button("Cancel").clicked() {
+                    self.cancel_render();
+                }
+            });
         } else {
             if let Some(duration) = self.render_time {
                 ui.label(format!("Rendering took {:?}", duration));
             }
-            if self.renderer.is_render_finished() && ui.button("Save...").clicked() {
+            if self.renderer.is_cancelled() {
+                ui.label("Render cancelled");
+            }
+            let has_image = self.renderer.is_render_finished() || self.renderer.is_cancelled();
+            if has_image && ui.button("Save...").clicked() {
                 self.save_current_image();
             }
         }
     }
 
+    /// Fields for the placement of the camera of the scene last rendered.
+    fn camera_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(scene) = &self.scene_config else {
+            return;
+        };
+        ui.heading("Camera");
+
+        let mut camera = scene.camera.keyframe(0.0);
+        // Drag fields at a speed that suits the size of the scene.
+        let speed = 0.005 * (camera.look_from - camera.look_at).length();
+        let mut changed = false;
+        egui::Grid::new("camera").num_columns(2).show(ui, |ui| {
+            ui.label("Look from");
+            changed |= vec3_field(ui, &mut camera.look_from, speed);
+            ui.end_row();
+
+            ui.label("Look at");
+            changed |= vec3_field(ui, &mut camera.look_at, speed);
+            ui.end_row();
+
+            ui.label("Field of view");
+            let vfov = egui::DragValue::new(&mut camera.vfov_degrees)
+                .clamp_range(1.0..=179.0)
+                .suffix("°");
+            changed |= ui.add(vfov).changed();
+            ui.end_row();
+
+            ui.label("Aperture");
+            let aperture = egui::DragValue::new(&mut camera.aperture)
+                .clamp_range(0.0..=f64::INFINITY)
+                .speed(0.01);
+            changed |= ui.add(aperture).changed();
+            ui.end_row();
+
+            ui.label("Focus distance");
+            let focus_dist = egui::DragValue::new(&mut camera.focus_dist)
+                .clamp_range(0.001..=f64::INFINITY)
+                .speed(speed);
+            changed |= ui.add(focus_dist).changed();
+            ui.end_row();
+        });
+        ui.label("Drag the image to orbit, with shift or the right button to pan, scroll to zoom.");
+
+        if changed {
+            self.move_camera(&camera);
+        }
+    }
+
+    /// The objects of the scene last rendered, with fields for their
+    /// parameters and those of their materials and textures. Edits render
+    /// the scene again.
+    fn inspector_panel(&mut self, ui: &mut egui::Ui) {
+        let (Some(inspector), Some(scene)) = (&mut self.inspector, &mut self.scene_config) else {
+            return;
+        };
+        ui.heading("Scene");
+
+        let camera = scene.camera.keyframe(0.0);
+        let speed = 0.005 * (camera.look_from - camera.look_at).length();
+        let mut edited = false;
+        for (i, node) in inspector.objects.iter_mut().enumerate() {
+            edited |= node_ui(ui, node, i, speed);
+        }
+
+        if edited && inspector.apply_edits(scene) {
+            self.render_scene(true);
+        }
+    }
+
+    fn tone_mapping_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Tone mapping");
+
+        let mut tone_mapping = *self.renderer.tone_mapping();
+        egui::ComboBox::from_label("Operator")
+            .selected_text(format!("{:?}", tone_mapping.operator))
+            .show_ui(ui, |ui| {
+                for operator in ToneMapOperator::value_variants() {
+                    ui.selectable_value(
+                        &mut tone_mapping.operator,
+                        *operator,
+                        format!("{operator:?}"),
+                    );
+                }
+            });
+        ui.add(egui::Slider::new(&mut tone_mapping.exposure, -8.0..=8.0).text("Exposure"));
+        if tone_mapping.operator == ToneMapOperator::ExtendedReinhard {
+            ui.add(
+                egui::Slider::new(&mut tone_mapping.white_point, 0.1..=64.0)
+                    .logarithmic(true)
+                    .text("White point"),
+            );
+        }
+
+        if tone_mapping != *self.renderer.tone_mapping() {
+            self.renderer.set_tone_mapping(tone_mapping);
+            self.texture_outdated = true;
+        }
+    }
+
     fn save_current_image(&self) {
         let file = rfd::FileDialog::new()
             .add_filter("png", &["png"])
             .add_filter("jpg", &["jpg"])
             .add_filter("ppm", &["ppm"])
+            .add_filter("OpenEXR", &["exr"])
+            .add_filter("Radiance HDR", &["hdr"])
+            .add_filter("PFM", &["pfm"])
             .save_file();
 
         if let Some(path) = file {
-            let image = self.renderer.get_image();
-            if let Some(image) = image {
-                let _ = image.save(path);
+            let image = self.renderer.get_float_image();
+            if let Err(e) = output::save_image(image, &path, self.renderer.tone_mapping()) {
+                eprintln!("Error saving {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Drag fields for the coordinates of `v`, returning whether one changed.
+fn vec3_field(ui: &mut egui::Ui, v: &mut Vec3, speed: f64) -> bool {
+    ui.horizontal(|ui| {
+        let mut changed = false;
+        for coordinate in &mut v.e {
+            changed |= ui
+                .add(egui::DragValue::new(coordinate).speed(speed))
+                .changed();
+        }
+        changed
+    })
+    .inner
+}
+
+/// A collapsible section for `node` with fields for its parameters and the
+/// sections of its parts, returning whether a parameter was edited.
+fn node_ui(ui: &mut egui::Ui, node: &mut Node, id: usize, speed: f64) -> bool {
+    let title = if node.role.is_empty() {
+        node.kind.to_string()
+    } else {
+        format!("{}: {}", node.role, node.kind)
+    };
+    if node.parameters().is_empty() && node.children().is_empty() {
+        ui.label(title);
+        return false;
+    }
+
+    egui::CollapsingHeader::new(title)
+        .id_source(id)
+        .show(ui, |ui| {
+            let mut edited = false;
+            egui::Grid::new("parameters").num_columns(2).show(ui, |ui| {
+                for i in 0..node.parameters().len() {
+                    let parameter = node.parameters()[i];
+                    ui.label(parameter.name);
+                    if let Some(value) = parameter_field(ui, &parameter, speed) {
+                        node.set(i, value);
+                        edited = true;
+                    }
+                    ui.end_row();
+                }
+            });
+            for (i, child) in node.children_mut().iter_mut().enumerate() {
+                edited |= node_ui(ui, child, i, speed);
             }
+            edited
+        })
+        .body_returned
+        .unwrap_or(false)
+}
+
+/// Fields for the value of `parameter`, returning the new value if the user
+/// changed it. Points are dragged at `speed`, numbers at a speed that suits
+/// their size.
+fn parameter_field(ui: &mut egui::Ui, parameter: &Parameter, speed: f64) -> Option<Value> {
+    // A drag value clamps what it shows and reports that as a change, so a
+    // value the scene set below the minimum widens the range to itself
+    // rather than being edited by just being shown.
+    let range = |value: f64| parameter.min.min(value)..=f64::INFINITY;
+    match parameter.value {
+        Value::Number(mut number) => {
+            let original = number;
+            let number_speed = (0.01 * number.abs()).max(0.001);
+            let field = egui::DragValue::new(&mut number)
+                .clamp_range(range(original))
+                .speed(number_speed);
+            let changed = ui.add(field).changed() && number != original;
+            changed.then_some(Value::Number(number))
+        }
+        Value::Color(mut color) => {
+            let original = color;
+            let changed = ui
+                .horizontal(|ui| {
+                    let mut changed = false;
+                    for channel in &mut color.e {
+                        let channel_range = range(*channel);
+                        let field = egui::DragValue::new(channel)
+                            .clamp_range(channel_range)
+                            .speed(0.01);
+                        changed |= ui.add(field).changed();
+                    }
+                    color_swatch(ui, &color);
+                    changed
+                })
+                .inner;
+            (changed && color.e != original.e).then_some(Value::Color(color))
         }
+        Value::Point(mut point) => vec3_field(ui, &mut point, speed).then_some(Value::Point(point)),
     }
 }
+
+/// A patch of `color`, scaled down to fit the display if brighter than white.
+fn color_swatch(ui: &mut egui::Ui, color: &Color) {
+    let brightest = color.x().max(color.y()).max(color.z()).max(1.0);
+    let [r, g, b] = color.e.map(|channel| (channel / brightest) as f32);
+    let size = egui::vec2(ui.spacing().interact_size.y, ui.spacing().interact_size.y);
+    egui::widgets::color_picker::show_color(ui, egui::Rgba::from_rgb(r, g, b), size);
+}
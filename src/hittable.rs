@@ -2,8 +2,11 @@ use std::sync::Arc;
 
 use crate::{
     aabb::Aabb,
+    inspector::{self, Node, Parameter, Part},
     material,
+    matrix::Matrix4,
     ray::{self, Ray},
+    rng::RenderRng,
     vec3::{self, Point3, Vec3},
 };
 
@@ -101,11 +104,44 @@ impl Default for HitRecord {
 }
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, _ray: &ray::Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        _ray: &ray::Ray,
+        _t_min: f64,
+        _t_max: f64,
+        _rec: &mut HitRecord,
+        _rng: &mut RenderRng,
+    ) -> bool {
         false
     }
 
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    /// Whether this object implements `pdf_value` and `random`, which it
+    /// needs to be sampled as a light.
+    fn can_sample(&self) -> bool {
+        false
+    }
+
+    /// Probability density of `random` choosing `direction` from `origin`
+    /// at `time`.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64) -> f64 {
+        0.0
+    }
+
+    /// Random direction from `origin` towards this object as it is at
+    /// `time`, used to sample lights directly.
+    ///
+    /// Panics unless `can_sample` is true.
+    fn random(&self, _origin: &Point3, _time: f64, _rng: &mut RenderRng) -> Vec3 {
+        panic!("Object can't be sampled as a light")
+    }
+
+    /// What the scene inspector shows of this object, or `None` if it can't
+    /// look into it.
+    fn inspect(&self) -> Option<Node> {
+        None
+    }
 }
 
 pub type HittablePtr = Arc<dyn Hittable>;
@@ -137,9 +173,16 @@ impl Hittable for Translate {
         }
     }
 
-    fn hit(&self, ray: &ray::Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        ray: &ray::Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
         let moved_ray = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
-        if !self.hittable.hit(&moved_ray, t_min, t_max, rec) {
+        if !self.hittable.hit(&moved_ray, t_min, t_max, rec, rng) {
             return false;
         }
 
@@ -149,6 +192,33 @@ impl Hittable for Translate {
 
         true
     }
+
+    fn can_sample(&self) -> bool {
+        self.hittable.can_sample()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        self.hittable
+            .pdf_value(&(*origin - self.offset), direction, time)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut RenderRng) -> Vec3 {
+        self.hittable.random(&(*origin - self.offset), time, rng)
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Translate",
+            vec![Parameter::point("Offset", self.offset)],
+            vec![inspector::object("Object", &self.hittable)],
+            |values, parts| {
+                Part::Object(Arc::new(Translate::new(
+                    &parts[0].object(),
+                    &values[0].point(),
+                )))
+            },
+        ))
+    }
 }
 
 pub struct RotateY {
@@ -203,6 +273,24 @@ impl RotateY {
             bounding_box,
         }
     }
+
+    /// `v` in the frame of the rotated object.
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() - self.sin_theta * v.z(),
+            v.y(),
+            self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+
+    /// `v` from the frame of the rotated object back in the world's.
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() + self.sin_theta * v.z(),
+            v.y(),
+            -self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
 }
 
 impl Hittable for RotateY {
@@ -210,34 +298,253 @@ impl Hittable for RotateY {
         self.bounding_box
     }
 
-    fn hit(&self, ray: &ray::Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let mut origin = ray.origin;
-        let mut direction = ray.direction;
+    fn hit(
+        &self,
+        ray: &ray::Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
+        let rotated_ray = Ray::new(
+            self.to_object(&ray.origin),
+            self.to_object(&ray.direction),
+            ray.time,
+        );
+
+        if !self.hittable.hit(&rotated_ray, t_min, t_max, rec, rng) {
+            return false;
+        }
+
+        rec.p = self.to_world(&rec.p);
+        let normal = self.to_world(&rec.normal);
+        rec.set_face_normal(&rotated_ray, &normal);
+
+        true
+    }
+
+    fn can_sample(&self) -> bool {
+        self.hittable.can_sample()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        self.hittable
+            .pdf_value(&self.to_object(origin), &self.to_object(direction), time)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut RenderRng) -> Vec3 {
+        self.to_world(&self.hittable.random(&self.to_object(origin), time, rng))
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let angle = self.sin_theta.atan2(self.cos_theta).to_degrees();
+        Some(Node::new(
+            "Rotate Y",
+            vec![Parameter::unbounded("Angle", angle)],
+            vec![inspector::object("Object", &self.hittable)],
+            |values, parts| {
+                Part::Object(Arc::new(RotateY::new(
+                    &parts[0].object(),
+                    values[0].number(),
+                )))
+            },
+        ))
+    }
+}
 
-        origin[0] = self.cos_theta * ray.origin[0] - self.sin_theta * ray.origin[2];
-        origin[2] = self.sin_theta * ray.origin[0] + self.cos_theta * ray.origin[2];
+/// Instance of a hittable placed by an arbitrary affine transform, such as a
+/// composition of [`Matrix4::translation`], [`Matrix4::rotation`] and
+/// [`Matrix4::scaling`].
+pub struct Transform {
+    hittable: HittablePtr,
+    matrix: Matrix4,
+    inverse: Matrix4,
+    /// Inverse-transpose of `matrix`, which maps normals.
+    normal_matrix: Matrix4,
+}
 
-        direction[0] = self.cos_theta * ray.direction[0] - self.sin_theta * ray.direction[2];
-        direction[2] = self.sin_theta * ray.direction[0] + self.cos_theta * ray.direction[2];
+impl Transform {
+    /// Panics if `matrix` is not invertible.
+    pub fn new(hittable: &HittablePtr, matrix: &Matrix4) -> Transform {
+        let inverse = matrix
+            .inverse()
+            .expect("Transform matrix should be invertible");
+        Transform {
+            hittable: Arc::clone(hittable),
+            matrix: *matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        }
+    }
+}
 
-        let rotated_ray = Ray::new(origin, direction, 0.0);
+impl Hittable for Transform {
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.hittable
+            .bounding_box(time0, time1)
+            .map(|bbox| bbox.transformed(&self.matrix))
+    }
 
-        if !self.hittable.hit(&rotated_ray, t_min, t_max, rec) {
+    fn hit(
+        &self,
+        ray: &ray::Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
+        // The direction is not normalized, so `t` means the same point along
+        // both rays.
+        let local_ray = Ray::new(
+            self.inverse.transform_point(&ray.origin),
+            self.inverse.transform_vector(&ray.direction),
+            ray.time,
+        );
+        if !self.hittable.hit(&local_ray, t_min, t_max, rec, rng) {
             return false;
         }
 
-        let mut p = rec.p;
-        let mut normal = rec.normal;
+        // The normal already faces against the local ray, and transforming
+        // both keeps the sign of their dot product, so `front_face` stays.
+        rec.p = self.matrix.transform_point(&rec.p);
+        rec.normal = vec3::unit_vector(&self.normal_matrix.transform_vector(&rec.normal));
 
-        p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-        p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
+        true
+    }
 
-        normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-        normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
+    fn can_sample(&self) -> bool {
+        self.hittable.can_sample()
+    }
 
-        rec.p = p;
-        rec.set_face_normal(&rotated_ray, &normal);
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let local_direction = self.inverse.transform_vector(&vec3::unit_vector(direction));
+        let local_pdf = self.hittable.pdf_value(
+            &self.inverse.transform_point(origin),
+            &local_direction,
+            time,
+        );
+        // Directions are spread over more or less solid angle by the
+        // transform, unless it is rigid.
+        local_pdf * self.inverse.linear_determinant().abs() / local_direction.length().powi(3)
+    }
 
-        true
+    fn random(&self, origin: &Point3, time: f64, rng: &mut RenderRng) -> Vec3 {
+        let local_origin = self.inverse.transform_point(origin);
+        self.matrix
+            .transform_vector(&self.hittable.random(&local_origin, time, rng))
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let matrix = self.matrix;
+        let translation = matrix.transform_point(&Point3::origin());
+        Some(Node::new(
+            "Transform",
+            vec![Parameter::point("Translation", translation)],
+            vec![inspector::object("Object", &self.hittable)],
+            move |values, parts| {
+                let shift = Matrix4::translation(&(values[0].point() - translation));
+                Part::Object(Arc::new(Transform::new(
+                    &parts[0].object(),
+                    &(shift * matrix),
+                )))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::MaterialPtr, moving_sphere::MovingSphere, rng, sphere::Sphere};
+
+    #[test]
+    fn test_transform() {
+        let material: MaterialPtr = Arc::new(material::Lambertian::new_from_color(&Vec3::new(
+            0.5, 0.5, 0.5,
+        )));
+        let sphere: HittablePtr = Arc::new(Sphere::new(Point3::origin(), 1.0, &material));
+        // An ellipsoid stretched along x, tilted by 45 degrees about z.
+        let matrix = Matrix4::translation(&Vec3::new(0.0, 0.0, -5.0))
+            * Matrix4::rotation(&Vec3::new(0.0, 0.0, 1.0), 45.0)
+            * Matrix4::scaling(&Vec3::new(2.0, 1.0, 1.0));
+        let transform = Transform::new(&sphere, &matrix);
+
+        let bbox = transform.bounding_box(0.0, 1.0).unwrap();
+        let half_width = 1.5 * 2f64.sqrt();
+        assert!((bbox.max().x() - half_width).abs() < 1e-9);
+        assert!((bbox.min().z() + 6.0).abs() < 1e-9);
+
+        let mut rec = HitRecord::empty();
+        let ray = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(transform.hit(&ray, 0.001, f64::INFINITY, &mut rec, &mut rng::unused_rng()));
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!(rec.front_face);
+
+        // Off the axes the normal of the ellipsoid x^2/4 + y^2 + z^2 = 1 is
+        // the gradient, (x/4, y, z), not the scaled sphere normal (x, y, z).
+        let scaled = Transform::new(&sphere, &Matrix4::scaling(&Vec3::new(2.0, 1.0, 1.0)));
+        let ray = Ray::new(Point3::new(5.0, 0.3, 0.4), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(scaled.hit(&ray, 0.001, f64::INFINITY, &mut rec, &mut rng::unused_rng()));
+        let p = rec.p;
+        let gradient = vec3::unit_vector(&Vec3::new(p.x() / 4.0, p.y(), p.z()));
+        assert!((rec.normal - gradient).length() < 1e-9);
+
+        // The ray time reaches objects inside the transform.
+        let moving: HittablePtr = Arc::new(MovingSphere::new(
+            Point3::origin(),
+            Point3::new(0.0, 10.0, 0.0),
+            0.0,
+            1.0,
+            1.0,
+            &material,
+        ));
+        let rotated = Transform::new(&moving, &Matrix4::rotation(&Vec3::new(1.0, 0.0, 0.0), 90.0));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 20.0), Vec3::new(0.0, 0.0, -1.0), 1.0);
+        assert!(rotated.hit(&ray, 0.001, f64::INFINITY, &mut rec, &mut rng::unused_rng()));
+        assert!((rec.p.z() - 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_transformed_lights() {
+        let material: MaterialPtr = Arc::new(material::DiffuseLight::new_from_color(&Vec3::new(
+            1.0, 1.0, 1.0,
+        )));
+        let sphere: HittablePtr = Arc::new(Sphere::new(Point3::origin(), 1.0, &material));
+        let origin = Point3::new(0.5, 0.5, 1.6);
+        let lights: [HittablePtr; 3] = [
+            Arc::new(Translate::new(&sphere, &Vec3::new(0.0, 1.0, 0.0))),
+            Arc::new(RotateY::new(
+                &(Arc::new(Translate::new(&sphere, &Vec3::new(1.0, 0.0, 0.0))) as HittablePtr),
+                30.0,
+            )),
+            Arc::new(Transform::new(
+                &sphere,
+                &(Matrix4::rotation(&Vec3::new(0.0, 0.0, 1.0), 30.0)
+                    * Matrix4::scaling(&Vec3::new(2.0, 0.5, 1.0))),
+            )),
+        ];
+
+        let mut rng = rng::sample_rng(0, 0, 0, 0);
+        for light in &lights {
+            assert!(light.can_sample());
+
+            // Sampled directions head for the light.
+            for _ in 0..100 {
+                let direction = light.random(&origin, 0.0, &mut rng);
+                let ray = Ray::new(origin, direction, 0.0);
+                let mut rec = HitRecord::empty();
+                assert!(light.hit(&ray, 0.001, f64::INFINITY, &mut rec, &mut rng));
+                assert!(light.pdf_value(&origin, &direction, 0.0) > 0.0);
+            }
+
+            // The density integrates to one over all directions.
+            let samples = 400000;
+            let total: f64 = (0..samples)
+                .map(|_| light.pdf_value(&origin, &vec3::random_unit_vector(&mut rng), 0.0))
+                .sum();
+            let integral = total * 4.0 * std::f64::consts::PI / samples as f64;
+            assert!((integral - 1.0).abs() < 0.03, "{integral}");
+        }
     }
 }
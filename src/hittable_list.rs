@@ -1,8 +1,16 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
 use crate::{
     aabb::Aabb,
     hittable::{HitRecord, Hittable, HittablePtr},
+    inspector::{self, Node, Part},
+    rng::RenderRng,
+    vec3::{Point3, Vec3},
 };
 
+#[derive(Clone)]
 pub struct HittableList {
     pub objects: Vec<HittablePtr>,
 }
@@ -17,6 +25,10 @@ impl HittableList {
     pub fn add(&mut self, object: HittablePtr) {
         self.objects.push(object);
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
 }
 
 impl Default for HittableList {
@@ -26,13 +38,20 @@ impl Default for HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, _ray: &crate::ray::Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        _ray: &crate::ray::Ray,
+        _t_min: f64,
+        _t_max: f64,
+        _rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
         let mut temp_rec = HitRecord::empty();
         let mut hit_anything = false;
         let mut closest_so_far = _t_max;
 
         for object in &self.objects {
-            if object.hit(_ray, _t_min, closest_so_far, &mut temp_rec) {
+            if object.hit(_ray, _t_min, closest_so_far, &mut temp_rec, rng) {
                 closest_so_far = temp_rec.t();
                 hit_anything = true;
                 _rec.copy_from(&temp_rec);
@@ -67,4 +86,37 @@ impl Hittable for HittableList {
 
         output
     }
+
+    fn can_sample(&self) -> bool {
+        !self.objects.is_empty() && self.objects.iter().all(|object| object.can_sample())
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction, time))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut RenderRng) -> Vec3 {
+        let index = rng.gen_range(0..self.objects.len());
+        self.objects[index].random(origin, time, rng)
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "List",
+            vec![],
+            self.objects
+                .iter()
+                .map(|object| inspector::object("Object", object))
+                .collect(),
+            |_, parts| {
+                Part::Object(Arc::new(HittableList {
+                    objects: parts.iter().map(Part::object).collect(),
+                }))
+            },
+        ))
+    }
 }
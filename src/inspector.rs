@@ -0,0 +1,381 @@
+//! Reading and editing the parameters of a scene's objects, materials and
+//! textures, for the GUI's scene inspector.
+//!
+//! Scene objects are shared and immutable, so an edit builds new objects in
+//! place of the edited one and of everything it is part of, leaving the rest
+//! of the scene as it was.
+
+use std::sync::Arc;
+
+use crate::{
+    hittable::HittablePtr,
+    material::MaterialPtr,
+    scenes::SceneConfig,
+    texture::TexturePtr,
+    vec3::{Color, Point3},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Number(f64),
+    Color(Color),
+    Point(Point3),
+}
+
+impl Value {
+    /// Panics if this is not a number.
+    pub fn number(&self) -> f64 {
+        match self {
+            Value::Number(number) => *number,
+            _ => panic!("Value should be a number"),
+        }
+    }
+
+    /// Panics if this is not a color.
+    pub fn color(&self) -> Color {
+        match self {
+            Value::Color(color) => *color,
+            _ => panic!("Value should be a color"),
+        }
+    }
+
+    /// Panics if this is not a point.
+    pub fn point(&self) -> Point3 {
+        match self {
+            Value::Point(point) => *point,
+            _ => panic!("Value should be a point"),
+        }
+    }
+}
+
+/// A named, editable value of an object, material or texture.
+#[derive(Debug, Clone, Copy)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub value: Value,
+    /// Smallest value a number or a color channel may be set to.
+    pub min: f64,
+}
+
+impl Parameter {
+    pub fn number(name: &'static str, value: f64, min: f64) -> Parameter {
+        Parameter {
+            name,
+            value: Value::Number(value),
+            min,
+        }
+    }
+
+    /// Any number, such as an angle.
+    pub fn unbounded(name: &'static str, value: f64) -> Parameter {
+        Parameter::number(name, value, f64::NEG_INFINITY)
+    }
+
+    pub fn color(name: &'static str, color: Color) -> Parameter {
+        Parameter {
+            name,
+            value: Value::Color(color),
+            min: 0.0,
+        }
+    }
+
+    pub fn point(name: &'static str, point: Point3) -> Parameter {
+        Parameter {
+            name,
+            value: Value::Point(point),
+            min: f64::NEG_INFINITY,
+        }
+    }
+}
+
+/// An object, material or texture of a scene.
+#[derive(Clone)]
+pub enum Part {
+    Object(HittablePtr),
+    Material(MaterialPtr),
+    Texture(TexturePtr),
+}
+
+impl Part {
+    /// Panics if this is not an object.
+    pub fn object(&self) -> HittablePtr {
+        match self {
+            Part::Object(object) => Arc::clone(object),
+            _ => panic!("Part should be an object"),
+        }
+    }
+
+    /// Panics if this is not a material.
+    pub fn material(&self) -> MaterialPtr {
+        match self {
+            Part::Material(material) => Arc::clone(material),
+            _ => panic!("Part should be a material"),
+        }
+    }
+
+    /// Panics if this is not a texture.
+    pub fn texture(&self) -> TexturePtr {
+        match self {
+            Part::Texture(texture) => Arc::clone(texture),
+            _ => panic!("Part should be a texture"),
+        }
+    }
+}
+
+/// Builds a part from the values of its parameters and its parts, in the
+/// order the node lists them.
+type Rebuild = Box<dyn Fn(&[Value], &[Part]) -> Part>;
+
+/// What the inspector shows of a part of the scene: its parameters and the
+/// parts it is made of.
+pub struct Node {
+    /// What the part is, such as "Sphere" or "Metal".
+    pub kind: &'static str,
+    /// What the part is to the one it belongs to, such as "Material".
+    pub role: &'static str,
+    parameters: Vec<Parameter>,
+    children: Vec<Node>,
+    rebuild: Option<Rebuild>,
+    /// The part as it is now.
+    part: Option<Part>,
+    edited: bool,
+}
+
+impl Node {
+    /// Node of a part with editable `parameters`, made of the parts shown by
+    /// `children`. `rebuild` makes the part anew after an edit.
+    pub fn new(
+        kind: &'static str,
+        parameters: Vec<Parameter>,
+        children: Vec<Node>,
+        rebuild: impl Fn(&[Value], &[Part]) -> Part + 'static,
+    ) -> Node {
+        Node {
+            kind,
+            role: "",
+            parameters,
+            children,
+            rebuild: Some(Box::new(rebuild)),
+            part: None,
+            edited: false,
+        }
+    }
+
+    /// Node of a part the inspector can't look into, which is kept as it is.
+    pub fn opaque(kind: &'static str) -> Node {
+        Node {
+            kind,
+            role: "",
+            parameters: Vec::new(),
+            children: Vec::new(),
+            rebuild: None,
+            part: None,
+            edited: false,
+        }
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    pub fn children_mut(&mut self) -> &mut [Node] {
+        &mut self.children
+    }
+
+    /// Change the value of the parameter at `index`.
+    pub fn set(&mut self, index: usize, value: Value) {
+        self.parameters[index].value = value;
+        self.edited = true;
+    }
+
+    /// Whether this part or one it is made of was edited.
+    pub fn is_edited(&self) -> bool {
+        self.edited || self.children.iter().any(Node::is_edited)
+    }
+
+    /// Build the part with its edits, making anew only the edited parts and
+    /// those made of them. The objects replaced are added to `replaced` with
+    /// their replacements.
+    fn apply_edits(&mut self, replaced: &mut Vec<(HittablePtr, HittablePtr)>) -> Part {
+        let part = self.part.clone().expect("Node should have its part");
+        if !self.is_edited() {
+            return part;
+        }
+        let Some(rebuild) = &self.rebuild else {
+            return part;
+        };
+
+        let values: Vec<Value> = self.parameters.iter().map(|p| p.value).collect();
+        let children: Vec<Part> = self
+            .children
+            .iter_mut()
+            .map(|child| child.apply_edits(replaced))
+            .collect();
+        let new_part = rebuild(&values, &children);
+        if let (Part::Object(old), Part::Object(new)) = (&part, &new_part) {
+            replaced.push((Arc::clone(old), Arc::clone(new)));
+        }
+
+        self.part = Some(new_part.clone());
+        self.edited = false;
+        new_part
+    }
+
+    fn with_part(mut self, role: &'static str, part: Part) -> Node {
+        self.role = role;
+        self.part = Some(part);
+        self
+    }
+}
+
+/// Node showing `object` as `role` of the part it belongs to.
+pub fn object(role: &'static str, object: &HittablePtr) -> Node {
+    object
+        .inspect()
+        .unwrap_or_else(|| Node::opaque("Object"))
+        .with_part(role, Part::Object(Arc::clone(object)))
+}
+
+/// Node showing `material` as `role` of the part it belongs to.
+pub fn material(role: &'static str, material: &MaterialPtr) -> Node {
+    material
+        .inspect()
+        .unwrap_or_else(|| Node::opaque("Material"))
+        .with_part(role, Part::Material(Arc::clone(material)))
+}
+
+/// Node showing `texture` as `role` of the part it belongs to.
+pub fn texture(role: &'static str, texture: &TexturePtr) -> Node {
+    texture
+        .inspect()
+        .unwrap_or_else(|| Node::opaque("Texture"))
+        .with_part(role, Part::Texture(Arc::clone(texture)))
+}
+
+/// `value`, or `previous` if `value` is zero, for a size that may be negative,
+/// such as the radius of a sphere turned inside out, but not vanish.
+pub fn nonzero(value: f64, previous: f64) -> f64 {
+    if value == 0.0 {
+        previous
+    } else {
+        value
+    }
+}
+
+/// Nodes of the objects of a scene, to edit them and apply the edits to it.
+pub struct SceneInspector {
+    pub objects: Vec<Node>,
+}
+
+impl SceneInspector {
+    pub fn new(scene: &SceneConfig) -> SceneInspector {
+        SceneInspector {
+            objects: scene
+                .world
+                .objects
+                .iter()
+                .map(|o| object("Object", o))
+                .collect(),
+        }
+    }
+
+    /// Replace the edited objects of `scene`, which the inspector was made
+    /// from, returning whether there were any.
+    ///
+    /// Lights are sampled through `scene.lights`, which holds the same
+    /// objects as the world, so those are replaced there too.
+    pub fn apply_edits(&mut self, scene: &mut SceneConfig) -> bool {
+        let mut replaced = Vec::new();
+        for (node, object) in self.objects.iter_mut().zip(&mut scene.world.objects) {
+            if node.is_edited() {
+                *object = node.apply_edits(&mut replaced).object();
+            }
+        }
+
+        for light in &mut scene.lights.objects {
+            if let Some((_, new)) = replaced.iter().find(|(old, _)| Arc::ptr_eq(old, light)) {
+                *light = Arc::clone(new);
+            }
+        }
+        !replaced.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::Translate, material::Metal, ray::Ray, rng, scenes::Scene, sphere::Sphere,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_apply_edits() {
+        let mut scene = SceneConfig::get_scene(&Scene::CornellBox);
+        let metal: MaterialPtr = Arc::new(Metal::new(&Color::new(0.8, 0.8, 0.8), 0.0));
+        let sphere: HittablePtr = Arc::new(Sphere::new(Point3::origin(), 10.0, &metal));
+        let moved: HittablePtr = Arc::new(Translate::new(&sphere, &Vec3::new(278.0, 90.0, 190.0)));
+        scene.world.objects.push(moved);
+        let untouched = Arc::clone(&scene.world.objects[0]);
+        let light_index = scene
+            .world
+            .objects
+            .iter()
+            .position(|o| Arc::ptr_eq(o, &scene.lights.objects[0]))
+            .unwrap();
+
+        let mut inspector = SceneInspector::new(&scene);
+        assert!(!inspector.apply_edits(&mut scene));
+
+        let translate = inspector.objects.last_mut().unwrap();
+        assert_eq!(translate.kind, "Translate");
+        let sphere = &mut translate.children_mut()[0];
+        assert_eq!(sphere.kind, "Sphere");
+        let metal = &mut sphere.children_mut()[0];
+        assert_eq!((metal.role, metal.kind), ("Material", "Metal"));
+        assert_eq!(metal.parameters()[1].name, "Fuzz");
+        metal.set(1, Value::Number(0.5));
+        sphere.set(1, Value::Number(20.0));
+        let light = &mut inspector.objects[light_index];
+        let emission = &mut light.children_mut()[0];
+        assert_eq!(emission.parameters()[0].name, "Strength");
+        emission.set(0, Value::Number(2.0));
+
+        assert!(inspector.apply_edits(&mut scene));
+        assert!(Arc::ptr_eq(&scene.world.objects[0], &untouched));
+        assert!(Arc::ptr_eq(
+            &scene.world.objects[light_index],
+            &scene.lights.objects[0]
+        ));
+
+        // The sphere is twice as large, still in its place.
+        let ray = Ray::new(Point3::new(278.0, 90.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let mut rec = crate::hittable::HitRecord::empty();
+        let edited = scene.world.objects.last().unwrap();
+        assert!(edited.hit(&ray, 0.0, f64::INFINITY, &mut rec, &mut rng::unused_rng()));
+        assert!((rec.p.z() - 170.0).abs() < 1e-9);
+
+        let inspector = SceneInspector::new(&scene);
+        let metal = &inspector.objects.last().unwrap().children()[0].children()[0];
+        assert_eq!(metal.parameters()[1].value.number(), 0.5);
+    }
+
+    #[test]
+    fn test_inside_out_sphere() {
+        // A hollow bubble's negative radius can be edited, but not to zero.
+        let metal: MaterialPtr = Arc::new(Metal::new(&Color::new(0.8, 0.8, 0.8), 0.0));
+        let bubble: HittablePtr = Arc::new(Sphere::new(Point3::origin(), -0.45, &metal));
+        let radius =
+            |object: &HittablePtr| object.inspect().unwrap().parameters()[1].value.number();
+        for (edit, expected) in [(0.0, -0.45), (-0.3, -0.3)] {
+            let mut node = object("Object", &bubble);
+            node.set(1, Value::Number(edit));
+            let edited = node.apply_edits(&mut Vec::new()).object();
+            assert_eq!(radius(&edited), expected);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Materials made of other materials: a blend of two chosen between at
+//! random, and a clear coat laid over another.
+
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    hittable::HitRecord,
+    inspector::{self, Node, Parameter, Part},
+    material::{self, Material, MaterialPtr, ScatterResult},
+    microfacet::{self, Ggx},
+    onb::Onb,
+    ray::Ray,
+    rng::RenderRng,
+    texture::{SolidColor, TexturePtr},
+    vec3::{self, Color, Point3},
+};
+
+/// Blend of two materials by a `weight` texture, read as its luminance,
+/// from 0 for all `first` to 1 for all `second`, such as a mask between
+/// rust and paint.
+///
+/// Each hit is shaded as one of the two, chosen at random by the weight,
+/// which the renderer does before shading through [`Material::choose`].
+pub struct MixMaterial {
+    pub first: MaterialPtr,
+    pub second: MaterialPtr,
+    pub weight: TexturePtr,
+}
+
+impl MixMaterial {
+    pub fn new(first: &MaterialPtr, second: &MaterialPtr, weight: &TexturePtr) -> MixMaterial {
+        MixMaterial {
+            first: Arc::clone(first),
+            second: Arc::clone(second),
+            weight: Arc::clone(weight),
+        }
+    }
+
+    pub fn new_from_weight(first: &MaterialPtr, second: &MaterialPtr, weight: f64) -> MixMaterial {
+        let weight: TexturePtr = Arc::new(SolidColor::new_grey(weight));
+        MixMaterial::new(first, second, &weight)
+    }
+
+    fn weight(&self, u: f64, v: f64, point: &Point3) -> f64 {
+        self.weight.scalar_value(u, v, point).clamp(0.0, 1.0)
+    }
+
+    fn pick(&self, rec: &HitRecord, rng: &mut RenderRng) -> &MaterialPtr {
+        if rng.gen::<f64>() < self.weight(rec.u, rec.v, &rec.p) {
+            &self.second
+        } else {
+            &self.first
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    /// Scatter as one of the two materials. The mix has no density of its
+    /// own to go with this, so renderers shade the material `choose` picks
+    /// instead.
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut RenderRng) -> Option<ScatterResult> {
+        self.pick(rec, rng).scatter(ray_in, rec, rng)
+    }
+
+    fn emitted(&self, u: f64, v: f64, point: &Point3) -> Color {
+        let weight = self.weight(u, v, point);
+        (1.0 - weight) * self.first.emitted(u, v, point) + weight * self.second.emitted(u, v, point)
+    }
+
+    fn choose(&self, rec: &HitRecord, rng: &mut RenderRng) -> Option<MaterialPtr> {
+        Some(Arc::clone(self.pick(rec, rng)))
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Mix",
+            Vec::new(),
+            vec![
+                inspector::material("First", &self.first),
+                inspector::material("Second", &self.second),
+                inspector::texture("Weight", &self.weight),
+            ],
+            |_, parts| {
+                Part::Material(Arc::new(MixMaterial::new(
+                    &parts[0].material(),
+                    &parts[1].material(),
+                    &parts[2].texture(),
+                )))
+            },
+        ))
+    }
+}
+
+/// A clear dielectric coat over a `base` material, such as varnish over
+/// wood. The coat reflects light off its GGX microfacets by the Fresnel
+/// equations, and the light it lets through is scattered by the base and
+/// weakened again by the coat on its way out.
+pub struct Coated {
+    pub base: MaterialPtr,
+    /// Index of refraction of the coat.
+    pub ir: f64,
+    /// Perceptual roughness of the coat from 0, a polished one, to 1.
+    pub roughness: TexturePtr,
+}
+
+impl Coated {
+    pub fn new(base: &MaterialPtr, index_of_refraction: f64, roughness: &TexturePtr) -> Coated {
+        Coated {
+            base: Arc::clone(base),
+            ir: index_of_refraction,
+            roughness: Arc::clone(roughness),
+        }
+    }
+
+    pub fn new_from_roughness(
+        base: &MaterialPtr,
+        index_of_refraction: f64,
+        roughness: f64,
+    ) -> Coated {
+        let roughness: TexturePtr = Arc::new(SolidColor::new_grey(roughness));
+        Coated::new(base, index_of_refraction, &roughness)
+    }
+
+    /// Fraction of the light leaving the base along `scattered` that gets
+    /// through the coat.
+    fn exit_transmittance(&self, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal.dot(&vec3::unit_vector(&scattered.direction));
+        1.0 - microfacet::fresnel_dielectric(cosine.abs(), self.ir)
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut RenderRng) -> Option<ScatterResult> {
+        let roughness = self.roughness.scalar_value(rec.u, rec.v, &rec.p);
+        let ggx = Ggx::from_roughness(roughness.clamp(0.0, 1.0));
+        let uvw = Onb::build_from_w(&rec.normal);
+        let wo = uvw.to_local(&-&vec3::unit_vector(&ray_in.direction));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        // Reflect off the coat as often as the sampled microfacet's Fresnel
+        // reflectance says, and otherwise enter it, so that neither needs
+        // weighing by it.
+        let m = ggx.sample_visible_normal(&wo, rng);
+        let reflectance = microfacet::fresnel_dielectric(wo.dot(&m), self.ir);
+        if rng.gen::<f64>() < reflectance {
+            let wi = vec3::reflect(&-&wo, &m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let weight = ggx.g2(&wo, &wi) / ggx.g1(&wo);
+            return Some(ScatterResult {
+                attenuation: Color::new(weight, weight, weight),
+                scattered: Ray::new(rec.p, uvw.local(&wi), ray_in.time),
+                pdf: None,
+            });
+        }
+
+        // Directions sampled along with the lights are only known to
+        // `scattering_pdf`, which weighs them by the way out instead.
+        let mut result = self.base.scatter(ray_in, rec, rng)?;
+        if result.pdf.is_none() {
+            result.attenuation =
+                self.exit_transmittance(rec, &result.scattered) * result.attenuation;
+        }
+        Some(result)
+    }
+
+    /// Density of the base, weakened by the coat on the way out.
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.exit_transmittance(rec, scattered) * self.base.scattering_pdf(ray_in, rec, scattered)
+    }
+
+    fn emitted(&self, u: f64, v: f64, point: &Point3) -> Color {
+        self.base.emitted(u, v, point)
+    }
+
+    /// A coat over a base that chooses is the same coat over its choice.
+    fn choose(&self, rec: &HitRecord, rng: &mut RenderRng) -> Option<MaterialPtr> {
+        let base = material::choose(&self.base, rec, rng)?;
+        Some(Arc::new(Coated::new(&base, self.ir, &self.roughness)))
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Coated",
+            vec![Parameter::number("Index of refraction", self.ir, 0.01)],
+            vec![
+                inspector::material("Base", &self.base),
+                inspector::texture("Roughness", &self.roughness),
+            ],
+            |values, parts| {
+                Part::Material(Arc::new(Coated::new(
+                    &parts[0].material(),
+                    values[0].number(),
+                    &parts[1].texture(),
+                )))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{
+            test_util::{normal_incidence_hit, TEST_SAMPLES},
+            DiffuseLight, Lambertian, Metal,
+        },
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_layered_materials() {
+        let (ray, rec, mut rng) = normal_incidence_hit();
+        let samples = TEST_SAMPLES;
+
+        // The second material is chosen as often as the weight says, and
+        // emission is blended by it.
+        let light: MaterialPtr = Arc::new(DiffuseLight::new_from_color(&Color::new(1.0, 1.0, 1.0)));
+        let black: MaterialPtr = Arc::new(Lambertian::new_from_color(&Color::origin()));
+        let mix: MaterialPtr = Arc::new(MixMaterial::new_from_weight(&light, &black, 0.25));
+        let chosen_black = (0..samples)
+            .filter(|_| {
+                let chosen = material::choose(&mix, &rec, &mut rng).unwrap();
+                Arc::ptr_eq(&chosen, &black)
+            })
+            .count();
+        let fraction = chosen_black as f64 / samples as f64;
+        assert!((fraction - 0.25).abs() < 0.01, "{fraction}");
+        assert!((mix.emitted(0.0, 0.0, &rec.p).x() - 0.75).abs() < 1e-12);
+
+        // A polished coat over a mirror reflects 4% off the coat, and of the
+        // rest the 4% the coat reflects back on the way out is lost.
+        let mirror: MaterialPtr = Arc::new(Metal::new(&Color::new(1.0, 1.0, 1.0), 0.0));
+        let coated = Coated::new_from_roughness(&mirror, 1.5, 0.0);
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            if let Some(result) = coated.scatter(&ray, &rec, &mut rng) {
+                sum += result.attenuation.x();
+            }
+        }
+        let albedo = sum / samples as f64;
+        assert!((albedo - (0.04 + 0.96 * 0.96)).abs() < 1e-3, "{albedo}");
+
+        // Over a diffuse base the coat weakens light leaving at a grazing
+        // angle more than light leaving straight up.
+        let coated: MaterialPtr = Arc::new(Coated::new_from_roughness(&black, 1.5, 0.0));
+        let up = Ray::new(rec.p, rec.normal, 0.0);
+        let grazing = Ray::new(rec.p, Vec3::new(1.0, 0.1, 0.0), 0.0);
+        let ratio = |scattered: &Ray| {
+            coated.scattering_pdf(&ray, &rec, scattered)
+                / black.scattering_pdf(&ray, &rec, scattered)
+        };
+        assert!((ratio(&up) - 0.96).abs() < 1e-9);
+        assert!(ratio(&grazing) < 0.7);
+
+        // A coat over a mix chooses through to a coat over its choice.
+        let coated_mix: MaterialPtr = Arc::new(Coated::new_from_roughness(&mix, 1.5, 0.0));
+        let chosen = material::choose(&coated_mix, &rec, &mut rng).unwrap();
+        assert_eq!(chosen.inspect().unwrap().kind, "Coated");
+        assert!(material::choose(&chosen, &rec, &mut rng).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+//! A ray tracer following the "Ray Tracing in One Weekend" series.
+//!
+//! Scenes are described by a [`scenes::SceneConfig`], either built in code,
+//! picked from the built-in [`scenes::Scene`]s or loaded from a TOML file with
+//! [`scene_file::load_scene_file`]. [`render::render`] turns one into a linear
+//! floating point image:
+//!
+//! ```no_run
+//! use rust_raytracing::{
+//!     render,
+//!     scenes::{Scene, SceneConfig},
+//! };
+//!
+//! let scene = SceneConfig::get_scene(&Scene::CornellBox);
+//! let image = render::render(&scene).expect("render failed");
+//! println!("{} x {}", image.width(), image.height());
+//! ```
+
+pub mod aabb;
+pub mod aarect;
+pub mod animated_transform;
+pub mod box_struct;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod constant_medium;
+pub mod hittable;
+pub mod hittable_list;
+pub mod inspector;
+pub mod layered;
+pub mod material;
+pub mod matrix;
+pub mod microfacet;
+pub mod moving_sphere;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod perlin;
+pub mod principled;
+pub mod quaternion;
+pub mod ray;
+pub mod render;
+pub mod rng;
+pub mod scene_file;
+pub mod scenes;
+pub mod sequence;
+pub mod sphere;
+pub mod texture;
+pub mod tiles;
+pub mod tone_map;
+pub mod triangle;
+pub mod triangle_mesh;
+pub mod vec3;
@@ -1,55 +1,91 @@
-use std::process;
+use std::{process, time::Duration};
 
 use clap::Parser;
 use cli::Cli;
+use rust_raytracing::{checkpoint, render, sequence, tone_map::ToneMapping};
 
-mod aabb;
-mod aarect;
-mod box_struct;
-mod bvh;
-mod camera;
 mod cli;
-pub mod color;
-pub mod constant_medium;
 mod gui;
-mod hittable;
-mod hittable_list;
-mod material;
-mod moving_sphere;
-mod perlin;
-mod ray;
-pub mod render;
-pub mod scenes;
-mod sphere;
-pub mod texture;
-pub mod vec3;
 
 fn main() {
     let cli = Cli::parse();
 
     match cli {
         Cli { no_gui: true, .. } => run_terminal(cli),
-        _ => run_gui(),
+        _ => run_gui(cli.tone_mapping()),
     }
 }
 
 fn run_terminal(args: Cli) {
-    let file_name = args.output.expect("Should have output");
-    let scene = args.scene.expect("Scene is required");
+    let scene_source = args.scene.as_ref().expect("Scene is required");
+    let sequence = args.sequence();
+    // Every frame's shutter times, for nested BVHs to bound moving objects
+    // over.
+    let times = match &sequence {
+        Some(sequence) => {
+            let (open, close) = sequence.time_span();
+            vec![open, close]
+        }
+        None => Vec::new(),
+    };
+    let mut scene = match scene_source.load(&times) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(1);
+        }
+    };
+    if let Err(e) = args.override_settings(&mut scene) {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+    let scene_hash = match scene_source.fingerprint() {
+        Ok(fingerprint) => checkpoint::scene_hash(&fingerprint, &scene),
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(1);
+        }
+    };
+    let resume_from = args.resume.as_ref().map(|path| {
+        checkpoint::Checkpoint::load(path)
+            .and_then(|checkpoint| {
+                checkpoint.check_matches(path, scene_hash, &scene)?;
+                Ok(checkpoint)
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            })
+    });
 
-    let config = render::RenderConfig::new(file_name, scene);
+    let file_name = args.output.clone().expect("Should have output");
+    let mut config = render::RenderConfig::new(file_name, scene);
+    config.tone_mapping = args.tone_mapping();
+    config.checkpoint_file = args.checkpoint_file();
+    config.heatmap_file = args.heatmap;
+    config.tile_order = args.tile_order;
+    config.checkpoint_interval = Duration::from_secs_f64(args.checkpoint_interval);
+    config.scene_hash = scene_hash;
+    config.resume_from = resume_from;
+    if let Some(threads) = args.threads {
+        config.threads = threads as usize;
+    }
 
-    if let Err(e) = render::render_and_save(config) {
+    let result = match sequence {
+        Some(sequence) => sequence::render_sequence(config, &sequence),
+        None => render::render_and_save(config),
+    };
+    if let Err(e) = result {
         eprintln!("Error: {e}");
         process::exit(1);
     }
 }
 
-fn run_gui() {
+fn run_gui(tone_mapping: ToneMapping) {
     let native_options = eframe::NativeOptions::default();
     let _ = eframe::run_native(
         "Raytracer",
         native_options,
-        Box::new(|cc| Box::new(gui::Gui::new(cc))),
+        Box::new(move |cc| Box::new(gui::Gui::new(cc, tone_mapping))),
     );
 }
@@ -1,27 +1,67 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
+
+use rand::Rng;
 
 use crate::{
     hittable::{self, HitRecord},
+    inspector::{self, Node, Parameter, Part},
+    microfacet::{self, Ggx},
+    onb::Onb,
+    pdf::{CosinePdf, Pdf, SpherePdf},
     ray::{self, Ray},
+    rng::RenderRng,
     texture::{SolidColor, TexturePtr},
-    vec3::{self, random_in_unit_sphere, Color, Point3},
+    vec3::{self, Color, Point3},
 };
 
 pub struct ScatterResult {
     pub attenuation: Color,
     pub scattered: Ray,
+    /// Distribution the scattered direction was drawn from. `None` for
+    /// specular materials and those that weigh their own samples, whose
+    /// scattered ray must be followed as is with `attenuation` as its weight.
+    pub pdf: Option<Box<dyn Pdf>>,
 }
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterResult>;
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut RenderRng) -> Option<ScatterResult>;
+
+    /// Probability density of scattering from `ray_in` into `scattered`.
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
 
     fn emitted(&self, _u: f64, _v: f64, _point: &Point3) -> Color {
         Color::origin()
     }
+
+    /// The material to shade the hit `rec` as in place of this one, for
+    /// materials that stand for a random choice between others. Choosing
+    /// once per hit, before shading, keeps `scatter`, `scattering_pdf` and
+    /// `emitted` on the same material.
+    fn choose(&self, _rec: &HitRecord, _rng: &mut RenderRng) -> Option<MaterialPtr> {
+        None
+    }
+
+    /// What the scene inspector shows of this material, or `None` if it
+    /// can't look into it.
+    fn inspect(&self) -> Option<Node> {
+        None
+    }
 }
 
 pub type MaterialPtr = Arc<dyn Material>;
 
+/// The material to shade the hit `rec` as in place of `material`, following
+/// its choices down to one that makes none, or `None` if it makes none.
+pub fn choose(material: &MaterialPtr, rec: &HitRecord, rng: &mut RenderRng) -> Option<MaterialPtr> {
+    let mut chosen = material.choose(rec, rng)?;
+    while let Some(next) = chosen.choose(rec, rng) {
+        chosen = next;
+    }
+    Some(chosen)
+}
+
 pub struct Lambertian {
     pub albedo: TexturePtr,
 }
@@ -40,21 +80,40 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray_in: &ray::Ray, rec: &hittable::HitRecord) -> Option<ScatterResult> {
-        let mut scatter_direction = rec.normal() + vec3::random_unit_vector();
-
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
-
-        let scattered = ray::Ray::new(rec.p, scatter_direction, ray_in.time);
+    fn scatter(
+        &self,
+        ray_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        rng: &mut RenderRng,
+    ) -> Option<ScatterResult> {
+        let pdf = CosinePdf::new(&rec.normal);
+        let scattered = ray::Ray::new(rec.p, pdf.generate(rng), ray_in.time);
         let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
 
         Option::Some(ScatterResult {
             attenuation,
             scattered,
+            pdf: Some(Box::new(pdf)),
         })
     }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal.dot(&vec3::unit_vector(&scattered.direction));
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Lambertian",
+            vec![],
+            vec![inspector::texture("Albedo", &self.albedo)],
+            |_, parts| Part::Material(Arc::new(Lambertian::new(&parts[0].texture()))),
+        ))
+    }
 }
 
 pub struct Metal {
@@ -72,35 +131,69 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &ray::Ray, rec: &hittable::HitRecord) -> Option<ScatterResult> {
+    fn scatter(
+        &self,
+        ray_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        rng: &mut RenderRng,
+    ) -> Option<ScatterResult> {
         let reflected = vec3::reflect(&vec3::unit_vector(&ray_in.direction), &rec.normal);
         let scattered = ray::Ray::new(
             rec.p,
-            reflected + self.fuzz * vec3::random_in_unit_sphere(),
+            reflected + self.fuzz * vec3::random_in_unit_sphere(rng),
             ray_in.time,
         );
         if scattered.direction.dot(&rec.normal) > 0.0 {
             Option::Some(ScatterResult {
                 attenuation: self.albedo,
                 scattered,
+                pdf: None,
             })
         } else {
             None
         }
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Metal",
+            vec![
+                Parameter::color("Albedo", self.albedo),
+                Parameter::number("Fuzz", self.fuzz, 0.0),
+            ],
+            vec![],
+            |values, _| {
+                Part::Material(Arc::new(Metal::new(&values[0].color(), values[1].number())))
+            },
+        ))
+    }
 }
 
 pub struct Dielectric {
     pub ir: f64,
+    /// Beer-Lambert absorption coefficient of each channel: light crossing a
+    /// distance `d` inside keeps `exp(-absorption * d)` of itself, which
+    /// tints thick glass more than thin.
+    ///
+    /// Light is only absorbed on its way to this object's own surface, so
+    /// a path that hits another object inside first, such as ice in a
+    /// drink, crosses the glass unabsorbed.
+    pub absorption: Color,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f64) -> Dielectric {
         Dielectric {
             ir: index_of_refraction,
+            absorption: Color::origin(),
         }
     }
 
+    /// This glass with the absorption coefficients `absorption`.
+    pub fn with_absorption(self, absorption: Color) -> Dielectric {
+        Dielectric { absorption, ..self }
+    }
+
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         // Use Shlick's approximation for reflectance.
         let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -110,8 +203,13 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &ray::Ray, rec: &hittable::HitRecord) -> Option<ScatterResult> {
-        let attenuation = vec3::Color::new(1.0, 1.0, 1.0);
+    fn scatter(
+        &self,
+        ray_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        rng: &mut RenderRng,
+    ) -> Option<ScatterResult> {
+        let attenuation = transmittance(&self.absorption, ray_in, rec);
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
@@ -124,7 +222,7 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::random::<f64>()
+            || Dielectric::reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>()
         {
             vec3::reflect(&unit_direction, &rec.normal)
         } else {
@@ -135,21 +233,260 @@ impl Material for Dielectric {
         Option::Some(ScatterResult {
             attenuation,
             scattered,
+            pdf: None,
+        })
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Dielectric",
+            vec![
+                Parameter::number("Index of refraction", self.ir, 0.01),
+                Parameter::color("Absorption", self.absorption),
+            ],
+            vec![],
+            |values, _| {
+                let dielectric =
+                    Dielectric::new(values[0].number()).with_absorption(values[1].color());
+                Part::Material(Arc::new(dielectric))
+            },
+        ))
+    }
+}
+
+/// Fraction of light left by the Beer-Lambert law after `ray_in` crossed a
+/// medium of absorption coefficients `absorption`, if it was inside the
+/// object it hit.
+fn transmittance(absorption: &Color, ray_in: &Ray, rec: &HitRecord) -> Color {
+    if rec.front_face {
+        return Color::new(1.0, 1.0, 1.0);
+    }
+    let distance = rec.t * ray_in.direction.length();
+    Color::new(
+        (-absorption.x() * distance).exp(),
+        (-absorption.y() * distance).exp(),
+        (-absorption.z() * distance).exp(),
+    )
+}
+
+/// Metals with measured complex indices of refraction, for
+/// [`RoughConductor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conductor {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl Conductor {
+    /// Real and imaginary parts of the index of refraction at the red, green
+    /// and blue wavelengths.
+    pub fn ior(&self) -> (Color, Color) {
+        let (eta, k) = match self {
+            Conductor::Gold => (
+                [0.143119, 0.374957, 1.442479],
+                [3.983160, 2.385721, 1.603215],
+            ),
+            Conductor::Copper => (
+                [0.200438, 0.924033, 1.102212],
+                [3.912949, 2.452848, 2.142188],
+            ),
+            Conductor::Aluminium => (
+                [1.657460, 0.880369, 0.521229],
+                [9.223869, 6.269523, 4.837001],
+            ),
+            Conductor::Silver => (
+                [0.155265, 0.116723, 0.138342],
+                [4.828181, 3.122249, 2.146961],
+            ),
+        };
+        (
+            Color::new(eta[0], eta[1], eta[2]),
+            Color::new(k[0], k[1], k[2]),
+        )
+    }
+}
+
+/// Metal with a rough surface of GGX microfacets, each reflecting by the
+/// Fresnel equations for the metal's complex index of refraction.
+pub struct RoughConductor {
+    /// Real part of the index of refraction, per channel.
+    pub eta: Color,
+    /// Extinction coefficient, the imaginary part of the index of refraction.
+    pub k: Color,
+    /// Perceptual roughness from 0, a mirror, to 1.
+    pub roughness: TexturePtr,
+}
+
+impl RoughConductor {
+    pub fn new(eta: &Color, k: &Color, roughness: &TexturePtr) -> RoughConductor {
+        RoughConductor {
+            eta: *eta,
+            k: *k,
+            roughness: Arc::clone(roughness),
+        }
+    }
+
+    pub fn new_from_conductor(conductor: Conductor, roughness: f64) -> RoughConductor {
+        let (eta, k) = conductor.ior();
+        let roughness: TexturePtr = Arc::new(SolidColor::new_grey(roughness));
+        RoughConductor::new(&eta, &k, &roughness)
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut RenderRng) -> Option<ScatterResult> {
+        let roughness = self.roughness.scalar_value(rec.u, rec.v, &rec.p);
+        let ggx = Ggx::from_roughness(roughness.clamp(0.0, 1.0));
+        let uvw = Onb::build_from_w(&rec.normal);
+        let wo = uvw.to_local(&-&vec3::unit_vector(&ray_in.direction));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let m = ggx.sample_visible_normal(&wo, rng);
+        let wi = vec3::reflect(&-&wo, &m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        // Sampling visible normals leaves only the Fresnel term and the
+        // shadowing of the reflected direction as the sample's weight.
+        let fresnel = microfacet::fresnel_conductor(wo.dot(&m), &self.eta, &self.k);
+        Some(ScatterResult {
+            attenuation: ggx.g2(&wo, &wi) / ggx.g1(&wo) * fresnel,
+            scattered: Ray::new(rec.p, uvw.local(&wi), ray_in.time),
+            pdf: None,
+        })
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Rough conductor",
+            vec![
+                Parameter::color("Eta", self.eta),
+                Parameter::color("K", self.k),
+            ],
+            vec![inspector::texture("Roughness", &self.roughness)],
+            |values, parts| {
+                Part::Material(Arc::new(RoughConductor::new(
+                    &values[0].color(),
+                    &values[1].color(),
+                    &parts[0].texture(),
+                )))
+            },
+        ))
+    }
+}
+
+/// Glass with a rough surface of GGX microfacets, each reflecting or
+/// refracting by the exact Fresnel equations (Walter et al., "Microfacet
+/// Models for Refraction through Rough Surfaces", 2007).
+pub struct RoughDielectric {
+    pub ir: f64,
+    /// Perceptual roughness from 0, smooth glass, to 1.
+    pub roughness: TexturePtr,
+    /// As for [`Dielectric::absorption`].
+    pub absorption: Color,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f64, roughness: &TexturePtr) -> RoughDielectric {
+        RoughDielectric {
+            ir: index_of_refraction,
+            roughness: Arc::clone(roughness),
+            absorption: Color::origin(),
+        }
+    }
+
+    /// This glass with the absorption coefficients `absorption`.
+    pub fn with_absorption(self, absorption: Color) -> RoughDielectric {
+        RoughDielectric { absorption, ..self }
+    }
+
+    pub fn new_from_roughness(index_of_refraction: f64, roughness: f64) -> RoughDielectric {
+        let roughness: TexturePtr = Arc::new(SolidColor::new_grey(roughness));
+        RoughDielectric::new(index_of_refraction, &roughness)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut RenderRng) -> Option<ScatterResult> {
+        let roughness = self.roughness.scalar_value(rec.u, rec.v, &rec.p);
+        let ggx = Ggx::from_roughness(roughness.clamp(0.0, 1.0));
+        // Index of refraction of the far side relative to the near side.
+        let eta = if rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        };
+        let uvw = Onb::build_from_w(&rec.normal);
+        let wo = uvw.to_local(&-&vec3::unit_vector(&ray_in.direction));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        // Reflect or refract off the sampled microfacet as often as its
+        // Fresnel reflectance says, which leaves only the shadowing of the
+        // new direction as the sample's weight.
+        let m = ggx.sample_visible_normal(&wo, rng);
+        let reflectance = microfacet::fresnel_dielectric(wo.dot(&m), eta);
+        let wi = if rng.gen::<f64>() < reflectance {
+            vec3::reflect(&-&wo, &m)
+        } else {
+            vec3::refract(&-&wo, &m, 1.0 / eta)
+        };
+        // Refracting must cross the surface and reflecting must not.
+        let reflected = wi.dot(&m) > 0.0;
+        if reflected != (wi.z() > 0.0) {
+            return None;
+        }
+
+        let weight = ggx.g2(&wo, &wi) / ggx.g1(&wo);
+        Some(ScatterResult {
+            attenuation: weight * transmittance(&self.absorption, ray_in, rec),
+            scattered: Ray::new(rec.p, uvw.local(&wi), ray_in.time),
+            pdf: None,
         })
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Rough dielectric",
+            vec![
+                Parameter::number("Index of refraction", self.ir, 0.01),
+                Parameter::color("Absorption", self.absorption),
+            ],
+            vec![inspector::texture("Roughness", &self.roughness)],
+            |values, parts| {
+                let dielectric = RoughDielectric::new(values[0].number(), &parts[0].texture())
+                    .with_absorption(values[1].color());
+                Part::Material(Arc::new(dielectric))
+            },
+        ))
+    }
 }
 
 pub struct DiffuseLight {
     emit: TexturePtr,
+    /// Factor the emitted color is scaled by.
+    strength: f64,
 }
 
 impl DiffuseLight {
     pub fn new(emit: &TexturePtr) -> DiffuseLight {
         DiffuseLight {
             emit: Arc::clone(emit),
+            strength: 1.0,
         }
     }
 
+    /// This light with its emission scaled by `strength`.
+    pub fn with_strength(self, strength: f64) -> DiffuseLight {
+        DiffuseLight { strength, ..self }
+    }
+
     pub fn new_from_color(color: &vec3::Color) -> DiffuseLight {
         let texture: TexturePtr = Arc::new(SolidColor::new(*color));
         DiffuseLight::new(&texture)
@@ -158,12 +495,30 @@ impl DiffuseLight {
 
 impl Material for DiffuseLight {
     fn emitted(&self, u: f64, v: f64, point: &Point3) -> Color {
-        self.emit.value(u, v, point)
+        self.strength * self.emit.value(u, v, point)
     }
 
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<ScatterResult> {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _rng: &mut RenderRng,
+    ) -> Option<ScatterResult> {
         None
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Diffuse light",
+            vec![Parameter::number("Strength", self.strength, 0.0)],
+            vec![inspector::texture("Emission", &self.emit)],
+            |values, parts| {
+                let light =
+                    DiffuseLight::new(&parts[0].texture()).with_strength(values[0].number());
+                Part::Material(Arc::new(light))
+            },
+        ))
+    }
 }
 
 pub struct Isotropic {
@@ -184,12 +539,121 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterResult> {
-        let scattered = Ray::new(rec.p, random_in_unit_sphere(), ray_in.time);
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut RenderRng) -> Option<ScatterResult> {
+        let pdf = SpherePdf;
+        let scattered = Ray::new(rec.p, pdf.generate(rng), ray_in.time);
         let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
         Some(ScatterResult {
             attenuation,
             scattered,
+            pdf: Some(Box::new(pdf)),
         })
     }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Isotropic",
+            vec![],
+            vec![inspector::texture("Albedo", &self.albedo)],
+            |_, parts| Part::Material(Arc::new(Isotropic::new(&parts[0].texture()))),
+        ))
+    }
+}
+
+/// Shared fixtures for the tests of materials.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::*;
+    use crate::{rng, vec3::Vec3};
+
+    /// Number of times the material tests scatter off the same hit to
+    /// average its result.
+    pub(crate) const TEST_SAMPLES: usize = 20000;
+
+    /// A ray falling straight down onto the origin of a surface facing up,
+    /// its hit there, and a random number generator to scatter off it with.
+    pub(crate) fn normal_incidence_hit() -> (Ray, HitRecord, RenderRng) {
+        let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let rec = HitRecord::new(Point3::origin(), 1.0, &ray, &normal, 0.0, 0.0);
+        (ray, rec, rng::sample_rng(0, 0, 0, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::{normal_incidence_hit, TEST_SAMPLES};
+    use super::*;
+    use crate::{rng, vec3::Vec3};
+
+    #[test]
+    fn test_rough_materials() {
+        let (ray, rec, mut rng) = normal_incidence_hit();
+
+        // Smooth glass reflects 4% of the light at normal incidence and lets
+        // the rest through unbent.
+        let glass = RoughDielectric::new_from_roughness(1.5, 0.0);
+        let samples = TEST_SAMPLES;
+        let mut reflected = 0;
+        for _ in 0..samples {
+            let result = glass.scatter(&ray, &rec, &mut rng).unwrap();
+            let direction = vec3::unit_vector(&result.scattered.direction);
+            if direction.y() > 0.0 {
+                reflected += 1;
+            } else {
+                assert!(direction.y() < -0.999);
+            }
+            assert!((result.attenuation.x() - 1.0).abs() < 1e-3);
+        }
+        let fraction = reflected as f64 / samples as f64;
+        assert!((fraction - 0.04).abs() < 0.01, "{fraction}");
+
+        // A rough metal reflects its Fresnel reflectance, colored as it is,
+        // less what its microfacets shadow.
+        let gold = RoughConductor::new_from_conductor(Conductor::Gold, 0.5);
+        let (eta, k) = Conductor::Gold.ior();
+        let normal_reflectance = microfacet::fresnel_conductor(1.0, &eta, &k);
+        let mut sum = Color::origin();
+        for _ in 0..TEST_SAMPLES {
+            if let Some(result) = gold.scatter(&ray, &rec, &mut rng) {
+                assert!(result.scattered.direction.y() > 0.0);
+                sum += result.attenuation;
+            }
+        }
+        let albedo = &sum / TEST_SAMPLES as f64;
+        for i in 0..3 {
+            let ratio = albedo[i] / normal_reflectance[i];
+            assert!(
+                (0.85..=1.0).contains(&ratio),
+                "{albedo} {normal_reflectance}"
+            );
+        }
+        let mirror = RoughConductor::new_from_conductor(Conductor::Gold, 0.0);
+        let result = mirror.scatter(&ray, &rec, &mut rng).unwrap();
+        assert!((result.attenuation - normal_reflectance).length() < 1e-3);
+    }
+
+    #[test]
+    fn test_absorption() {
+        let glass = Dielectric::new(1.5).with_absorption(Color::new(0.5, 0.0, 1.0));
+        let mut rng = rng::sample_rng(0, 0, 0, 0);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+
+        // Entering the glass absorbs nothing, leaving it after 2 units
+        // absorbs by the distance travelled inside.
+        let entering = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = HitRecord::new(Point3::origin(), 1.0, &entering, &normal, 0.0, 0.0);
+        let result = glass.scatter(&entering, &rec, &mut rng).unwrap();
+        assert!((result.attenuation - Color::new(1.0, 1.0, 1.0)).length() < 1e-12);
+
+        let leaving = Ray::new(Point3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.5, 0.0), 0.0);
+        let rec = HitRecord::new(Point3::origin(), 4.0, &leaving, &normal, 0.0, 0.0);
+        let result = glass.scatter(&leaving, &rec, &mut rng).unwrap();
+        let expected = Color::new((-1.0f64).exp(), 1.0, (-2.0f64).exp());
+        assert!((result.attenuation - expected).length() < 1e-12);
+    }
 }
@@ -0,0 +1,216 @@
+use std::ops;
+
+use crate::vec3::{unit_vector, Point3, Vec3};
+
+/// Row-major 4x4 matrix of an affine transform acting on column vectors.
+///
+/// Transforms compose right to left: `a * b` applies `b` first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Matrix4 {
+        Matrix4 { m }
+    }
+
+    pub fn identity() -> Matrix4 {
+        Matrix4::scaling(&Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: &Vec3) -> Matrix4 {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: &Vec3) -> Matrix4 {
+        Matrix4::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Counterclockwise rotation by `angle` degrees about `axis`, looking
+    /// down the axis towards the origin.
+    pub fn rotation(axis: &Vec3, angle: f64) -> Matrix4 {
+        let a = unit_vector(axis);
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let radians = angle.to_radians();
+        let (sin, cos) = radians.sin_cos();
+        let t = 1.0 - cos;
+
+        Matrix4::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Determinant of the linear part, by which the transform scales volumes.
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4::new(m)
+    }
+
+    /// Inverse by Gauss-Jordan elimination with partial pivoting, or `None`
+    /// if the matrix is singular.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inverse = Matrix4::identity().m;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .expect("range is not empty");
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= scale;
+                inverse[column][k] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[column][k];
+                    inverse[row][k] -= factor * inverse[column][k];
+                }
+            }
+        }
+
+        Some(Matrix4::new(inverse))
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        Point3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    /// Transform a direction, ignoring the translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Matrix4::identity()
+    }
+}
+
+impl ops::Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4::new(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_rotation_and_composition() {
+        let rotation = Matrix4::rotation(&Vec3::new(0.0, 0.0, 2.0), 90.0);
+        assert_near(
+            &rotation.transform_vector(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 1.0, 0.0),
+        );
+
+        // Matches the rotation of `RotateY`.
+        let rotation = Matrix4::rotation(&Vec3::new(0.0, 1.0, 0.0), 90.0);
+        assert_near(
+            &rotation.transform_vector(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 0.0, -1.0),
+        );
+
+        let matrix = Matrix4::translation(&Vec3::new(1.0, 2.0, 3.0))
+            * Matrix4::scaling(&Vec3::new(2.0, 1.0, 1.0));
+        assert_near(
+            &matrix.transform_point(&Vec3::new(1.0, 1.0, 1.0)),
+            &Vec3::new(3.0, 3.0, 4.0),
+        );
+        assert_near(
+            &matrix.transform_vector(&Vec3::new(1.0, 1.0, 1.0)),
+            &Vec3::new(2.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn test_inverse() {
+        let matrix = Matrix4::translation(&Vec3::new(1.0, -2.0, 3.0))
+            * Matrix4::rotation(&Vec3::new(1.0, 1.0, 0.0), 37.0)
+            * Matrix4::scaling(&Vec3::new(2.0, 0.5, 3.0));
+        let inverse = matrix.inverse().expect("matrix should be invertible");
+
+        let p = Point3::new(0.3, 4.0, -1.5);
+        assert_near(&inverse.transform_point(&matrix.transform_point(&p)), &p);
+        assert_near(&matrix.transform_point(&inverse.transform_point(&p)), &p);
+
+        let singular = Matrix4::scaling(&Vec3::new(1.0, 0.0, 1.0));
+        assert!(singular.inverse().is_none());
+    }
+}
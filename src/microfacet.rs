@@ -0,0 +1,215 @@
+//! The GGX (Trowbridge-Reitz) microfacet distribution and Fresnel
+//! reflectance, for rough materials.
+//!
+//! Directions are given in the local frame of the surface, where the normal
+//! is +z.
+
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{
+    rng::RenderRng,
+    vec3::{self, Color, Vec3},
+};
+
+/// Smallest `alpha`, below which the distribution is too sharp for floating
+/// point and is treated as this near-mirror.
+const MIN_ALPHA: f64 = 1e-3;
+
+/// Isotropic GGX distribution of microfacet normals.
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    /// Distribution for a perceptual `roughness` from 0 (smooth) to 1, whose
+    /// square is the width `alpha` of the distribution.
+    pub fn from_roughness(roughness: f64) -> Ggx {
+        Ggx {
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+        }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Density of microfacets with normal `m`, per unit of projected area.
+    pub fn d(&self, m: &Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let cos2 = m.z() * m.z();
+        let denominator = cos2 * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function for the microfacets hidden from `w`.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of the microfacets facing `w` that are visible from it.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both `wo` and `wi`, with
+    /// height-correlated masking and shadowing.
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Sample a microfacet normal from those visible from `wo`, which must be
+    /// above the surface (Heitz, "Sampling the GGX Distribution of Visible
+    /// Normals", 2018).
+    pub fn sample_visible_normal(&self, wo: &Vec3, rng: &mut RenderRng) -> Vec3 {
+        // Stretch the view so that the distribution becomes a hemisphere.
+        let v = vec3::unit_vector(&Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+        let length2 = v.x() * v.x() + v.y() * v.y();
+        let t1 = if length2 > 0.0 {
+            &Vec3::new(-v.y(), v.x(), 0.0) / length2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross(&t1);
+
+        // Sample the projected area of the hemisphere seen from `v`.
+        let r = rng.gen::<f64>().sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        // Unstretch back to the distribution.
+        vec3::unit_vector(&Vec3::new(
+            self.alpha * n.x(),
+            self.alpha * n.y(),
+            n.z().max(0.0),
+        ))
+    }
+
+    /// Density of `sample_visible_normal` choosing `m` when seen from `wo`.
+    pub fn visible_normal_pdf(&self, wo: &Vec3, m: &Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z()
+    }
+}
+
+/// Reflectance of unpolarized light arriving at `cos_i` to the normal of a
+/// smooth interface into a medium of relative index of refraction `eta`.
+/// Light that cannot refract is reflected entirely.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_s = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_p = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (r_s * r_s + r_p * r_p)
+}
+
+/// Schlick's approximation of the Fresnel reflectance of a surface
+/// reflecting `f0` at normal incidence, per channel.
+pub fn fresnel_schlick(f0: &Color, cos_i: f64) -> Color {
+    let grazing = (1.0 - cos_i.clamp(0.0, 1.0)).powi(5);
+    *f0 + grazing * (Color::new(1.0, 1.0, 1.0) - *f0)
+}
+
+/// Reflectance of unpolarized light arriving at `cos_i` to the normal of a
+/// conductor with complex index of refraction `eta + i k`, per channel.
+pub fn fresnel_conductor(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let channel = |eta: f64, k: f64| {
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_i * a;
+        let r_s = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let r_p = r_s * (t3 - t4) / (t3 + t4);
+        0.5 * (r_s + r_p)
+    };
+    Color::new(
+        channel(eta.x(), k.x()),
+        channel(eta.y(), k.y()),
+        channel(eta.z(), k.z()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng;
+
+    #[test]
+    fn test_ggx() {
+        let ggx = Ggx::from_roughness(0.5);
+
+        // The projected microfacet area adds up to the macrosurface's.
+        let steps = 2000;
+        let d_theta = 0.5 * PI / steps as f64;
+        let projected_area: f64 = (0..steps)
+            .map(|i| {
+                let theta = (i as f64 + 0.5) * d_theta;
+                let m = Vec3::new(theta.sin(), 0.0, theta.cos());
+                ggx.d(&m) * theta.cos() * theta.sin() * d_theta * 2.0 * PI
+            })
+            .sum();
+        assert!((projected_area - 1.0).abs() < 1e-3, "{projected_area}");
+
+        // The density of visible normals integrates to one, and the ones
+        // sampled face the viewer.
+        let wo = vec3::unit_vector(&Vec3::new(0.6, 0.2, 0.5));
+        let d_phi = 2.0 * PI / steps as f64;
+        let total: f64 = (0..steps * steps)
+            .map(|i| {
+                let theta = ((i / steps) as f64 + 0.5) * d_theta;
+                let phi = ((i % steps) as f64 + 0.5) * d_phi;
+                let m = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                ggx.visible_normal_pdf(&wo, &m) * theta.sin() * d_theta * d_phi
+            })
+            .sum();
+        assert!((total - 1.0).abs() < 1e-3, "{total}");
+
+        let mut rng = rng::sample_rng(1, 2, 3, 0);
+        for _ in 0..1000 {
+            let m = ggx.sample_visible_normal(&wo, &mut rng);
+            assert!((m.length() - 1.0).abs() < 1e-9);
+            assert!(m.z() >= 0.0 && wo.dot(&m) >= 0.0);
+            assert!(ggx.visible_normal_pdf(&wo, &m) > 0.0);
+        }
+        assert!(ggx.g2(&wo, &wo) <= ggx.g1(&wo));
+
+        // Normal incidence on glass reflects 4%, grazing light all of it.
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+
+        // With no extinction a conductor is a dielectric.
+        let eta = Color::new(1.5, 1.5, 1.5);
+        let reflectance = fresnel_conductor(0.7, &eta, &Color::origin());
+        assert!((reflectance.x() - fresnel_dielectric(0.7, 1.5)).abs() < 1e-12);
+    }
+}
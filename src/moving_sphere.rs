@@ -2,7 +2,10 @@ use std::sync::Arc;
 
 use crate::{
     aabb::Aabb,
-    hittable, material, ray,
+    hittable,
+    inspector::{self, Node, Parameter, Part},
+    material, ray,
+    rng::RenderRng,
     vec3::{Point3, Vec3},
 };
 
@@ -41,7 +44,14 @@ impl MovingSphere {
 }
 
 impl hittable::Hittable for MovingSphere {
-    fn hit(&self, ray: &ray::Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+    fn hit(
+        &self,
+        ray: &ray::Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut hittable::HitRecord,
+        _rng: &mut RenderRng,
+    ) -> bool {
         let oc = ray.origin - self.center(ray.time);
         let a = ray.direction.length_squared();
         let half_b = oc.dot(&ray.direction);
@@ -83,4 +93,27 @@ impl hittable::Hittable for MovingSphere {
         let output_box = box0.surrounding_box(&box1);
         Some(output_box)
     }
+
+    fn inspect(&self) -> Option<Node> {
+        let (time0, time1, radius) = (self.time0, self.time1, self.radius);
+        Some(Node::new(
+            "Moving sphere",
+            vec![
+                Parameter::point("Start center", self.center0),
+                Parameter::point("End center", self.center1),
+                Parameter::unbounded("Radius", self.radius),
+            ],
+            vec![inspector::material("Material", &self.material)],
+            move |values, parts| {
+                Part::Object(Arc::new(MovingSphere::new(
+                    values[0].point(),
+                    values[1].point(),
+                    time0,
+                    time1,
+                    inspector::nonzero(values[2].number(), radius),
+                    &parts[0].material(),
+                )))
+            },
+        ))
+    }
 }
@@ -0,0 +1,44 @@
+use crate::vec3::{unit_vector, Vec3};
+
+/// Orthonormal basis.
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    /// Build a basis whose `w` axis points along `n`.
+    pub fn build_from_w(n: &Vec3) -> Onb {
+        let w = unit_vector(n);
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(&w.cross(&a));
+        let u = w.cross(&v);
+        Onb { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> &Vec3 {
+        &self.axis[0]
+    }
+
+    pub fn v(&self) -> &Vec3 {
+        &self.axis[1]
+    }
+
+    pub fn w(&self) -> &Vec3 {
+        &self.axis[2]
+    }
+
+    /// Transform a vector given in this basis into world coordinates.
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.u() + a.y() * self.v() + a.z() * self.w()
+    }
+
+    /// Transform a vector given in world coordinates into this basis, the
+    /// inverse of `local`.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u()), a.dot(self.v()), a.dot(self.w()))
+    }
+}
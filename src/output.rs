@@ -0,0 +1,109 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use image::{codecs::hdr::HdrEncoder, ImageFormat, Rgb, Rgb32FImage, RgbImage};
+
+use crate::{render::SampleCounts, tone_map::ToneMapping};
+
+/// Save a linear image, choosing the format by the extension of `file_name`.
+///
+/// OpenEXR, Radiance `.hdr` and PFM files store the unclamped colors. Any
+/// other extension is tone mapped and written as an 8-bit image through the
+/// `image` crate.
+pub fn save_image(
+    image: &Rgb32FImage,
+    file_name: &Path,
+    tone_mapping: &ToneMapping,
+) -> Result<(), Box<dyn Error>> {
+    let extension = file_name
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("exr") => image.save_with_format(file_name, ImageFormat::OpenExr)?,
+        Some("hdr") => {
+            let writer = BufWriter::new(File::create(file_name)?);
+            let pixels: Vec<Rgb<f32>> = image.pixels().copied().collect();
+            HdrEncoder::new(writer).encode(
+                &pixels,
+                image.width() as usize,
+                image.height() as usize,
+            )?;
+        }
+        Some("pfm") => {
+            let mut writer = BufWriter::new(File::create(file_name)?);
+            write_pfm(image, &mut writer)?;
+            writer.flush()?;
+        }
+        _ => tone_mapping.apply(image).save(file_name)?,
+    }
+
+    Ok(())
+}
+
+/// Write a color Portable Float Map. Scanlines are stored bottom to top and
+/// the negative scale marks the floats as little-endian.
+fn write_pfm(image: &Rgb32FImage, out: &mut impl Write) -> std::io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for y in (0..image.height()).rev() {
+        for x in 0..image.width() {
+            for channel in image.get_pixel(x, y).0 {
+                out.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Color pixels by how many samples they took, from black for the fewest
+/// through blue, red and yellow to white for the most.
+pub fn sample_heatmap(sample_counts: &SampleCounts) -> RgbImage {
+    const RAMP: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.1, 0.1, 0.8],
+        [0.9, 0.1, 0.1],
+        [1.0, 0.9, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+
+    let min = sample_counts.iter().copied().min().unwrap_or(0);
+    let max = sample_counts.iter().copied().max().unwrap_or(0);
+    let range = (max - min).max(1) as f32;
+
+    RgbImage::from_fn(sample_counts.width(), sample_counts.height(), |x, y| {
+        let t = (sample_counts.get_pixel(x, y)[0] - min) as f32 / range;
+        let position = t * (RAMP.len() - 1) as f32;
+        let index = (position as usize).min(RAMP.len() - 2);
+        let fraction = position - index as f32;
+        let [start, end] = [RAMP[index], RAMP[index + 1]];
+        Rgb([0, 1, 2].map(|c| (255.0 * (start[c] + fraction * (end[c] - start[c])) + 0.5) as u8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_pfm() {
+        let mut image = Rgb32FImage::new(2, 1);
+        image.put_pixel(0, 0, Rgb([15.0, 0.5, 0.0]));
+        image.put_pixel(1, 0, Rgb([1.0, 2.0, 3.0]));
+
+        let mut bytes = Vec::new();
+        write_pfm(&image, &mut bytes).unwrap();
+
+        let header = b"PF\n2 1\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(floats, [15.0, 0.5, 0.0, 1.0, 2.0, 3.0]);
+    }
+}
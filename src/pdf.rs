@@ -0,0 +1,113 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{
+    hittable::Hittable,
+    onb::Onb,
+    rng::RenderRng,
+    vec3::{self, Point3, Vec3},
+};
+
+/// Probability density function over directions.
+pub trait Pdf {
+    /// Density of sampling `direction`.
+    fn value(&self, direction: &Vec3) -> f64;
+
+    /// Sample a direction from the distribution.
+    fn generate(&self, rng: &mut RenderRng) -> Vec3;
+}
+
+/// Cosine-weighted distribution over the hemisphere around a normal.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: &Vec3) -> CosinePdf {
+        CosinePdf {
+            uvw: Onb::build_from_w(w),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = vec3::unit_vector(direction).dot(self.uvw.w());
+        if cosine <= 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+
+    fn generate(&self, rng: &mut RenderRng) -> Vec3 {
+        self.uvw.local(&vec3::random_cosine_direction(rng))
+    }
+}
+
+/// Uniform distribution over all directions.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, rng: &mut RenderRng) -> Vec3 {
+        vec3::random_unit_vector(rng)
+    }
+}
+
+/// Distribution of directions from `origin` towards an object as it is at
+/// `time`.
+pub struct HittablePdf<'a> {
+    object: &'a dyn Hittable,
+    origin: Point3,
+    time: f64,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(object: &'a dyn Hittable, origin: &Point3, time: f64) -> HittablePdf<'a> {
+        HittablePdf {
+            object,
+            origin: *origin,
+            time,
+        }
+    }
+}
+
+impl<'a> Pdf for HittablePdf<'a> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.object.pdf_value(&self.origin, direction, self.time)
+    }
+
+    fn generate(&self, rng: &mut RenderRng) -> Vec3 {
+        self.object.random(&self.origin, self.time, rng)
+    }
+}
+
+/// Even mix of two distributions.
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(pdf0: &'a dyn Pdf, pdf1: &'a dyn Pdf) -> MixturePdf<'a> {
+        MixturePdf { pdfs: [pdf0, pdf1] }
+    }
+}
+
+impl<'a> Pdf for MixturePdf<'a> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.pdfs[0].value(direction) + 0.5 * self.pdfs[1].value(direction)
+    }
+
+    fn generate(&self, rng: &mut RenderRng) -> Vec3 {
+        if rng.gen::<f64>() < 0.5 {
+            self.pdfs[0].generate(rng)
+        } else {
+            self.pdfs[1].generate(rng)
+        }
+    }
+}
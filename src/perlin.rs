@@ -1,8 +1,11 @@
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
 use crate::vec3::{unit_vector, Point3, Vec3};
 
 const POINT_COUNT: usize = 256;
+/// Seed of the noise made by `Perlin::default`.
+const DEFAULT_SEED: u64 = 0;
+
 pub struct Perlin {
     ran_vec: Vec<Vec3>,
     perm_x: [i32; POINT_COUNT],
@@ -11,15 +14,15 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Perlin {
+    pub fn new(rng: &mut impl Rng) -> Perlin {
         let mut ran_vec = Vec::with_capacity(POINT_COUNT);
         for _ in 0..POINT_COUNT {
-            ran_vec.push(unit_vector(&Vec3::random_range(-1.0, 1.0)));
+            ran_vec.push(unit_vector(&Vec3::random_range(-1.0, 1.0, rng)));
         }
 
-        let perm_x = Perlin::perlin_generate_perm();
-        let perm_y = Perlin::perlin_generate_perm();
-        let perm_z = Perlin::perlin_generate_perm();
+        let perm_x = Perlin::perlin_generate_perm(rng);
+        let perm_y = Perlin::perlin_generate_perm(rng);
+        let perm_z = Perlin::perlin_generate_perm(rng);
 
         Perlin {
             ran_vec,
@@ -71,7 +74,7 @@ impl Perlin {
         accum.abs()
     }
 
-    fn perlin_generate_perm() -> [i32; POINT_COUNT] {
+    fn perlin_generate_perm(rng: &mut impl Rng) -> [i32; POINT_COUNT] {
         let mut p: [i32; POINT_COUNT] = [0; POINT_COUNT];
 
         #[allow(clippy::needless_range_loop)]
@@ -79,13 +82,12 @@ impl Perlin {
             p[i] = i as i32;
         }
 
-        Perlin::permutate(&mut p);
+        Perlin::permutate(&mut p, rng);
 
         p
     }
 
-    fn permutate(p: &mut [i32; POINT_COUNT]) {
-        let mut rng = rand::thread_rng();
+    fn permutate(p: &mut [i32; POINT_COUNT], rng: &mut impl Rng) {
         for i in (1..POINT_COUNT).rev() {
             let target = rng.gen_range(0..i);
             p.swap(i, target);
@@ -141,6 +143,6 @@ impl Perlin {
 
 impl Default for Perlin {
     fn default() -> Self {
-        Self::new()
+        Self::new(&mut rand_pcg::Pcg32::seed_from_u64(DEFAULT_SEED))
     }
 }
@@ -0,0 +1,320 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use rand::Rng;
+
+use crate::{
+    color,
+    hittable::HitRecord,
+    inspector::{self, Node, Parameter, Part},
+    material::{Material, ScatterResult},
+    microfacet::{self, Ggx},
+    onb::Onb,
+    pdf::{CosinePdf, Pdf},
+    ray::Ray,
+    rng::RenderRng,
+    texture::{SolidColor, TexturePtr},
+    vec3::{self, Color},
+};
+
+/// Reflectance at normal incidence of the clearcoat, a layer of index of
+/// refraction 1.5.
+const CLEARCOAT_F0: f64 = 0.04;
+
+/// Material described by the parameters of Disney's principled BSDF
+/// (Burley, "Physically Based Shading at Disney", 2012), each of them a
+/// texture. Scalar parameters range from 0 to 1 and are read as the
+/// luminance of their texture.
+///
+/// The material is a clearcoat over a blend of rough glass, by
+/// `transmission`, and an opaque base of GGX specular reflection over a
+/// diffuse and sheen layer, by `metallic`. Each scatter picks one of these
+/// lobes at random.
+pub struct Principled {
+    pub base_color: TexturePtr,
+    /// 0 for a dielectric, 1 for a metal colored by `base_color`.
+    pub metallic: TexturePtr,
+    pub roughness: TexturePtr,
+    /// Specular reflectance of the dielectric, 0.5 being 4%, that of an
+    /// index of refraction of 1.5.
+    pub specular: TexturePtr,
+    /// How much the dielectric's specular reflection takes the hue of
+    /// `base_color`.
+    pub specular_tint: TexturePtr,
+    /// Extra reflection at grazing angles, as of cloth.
+    pub sheen: TexturePtr,
+    pub sheen_tint: TexturePtr,
+    /// Strength of a clear, glossy coat over the material.
+    pub clearcoat: TexturePtr,
+    pub clearcoat_roughness: TexturePtr,
+    /// 0 for an opaque dielectric, 1 for glass tinted by `base_color`.
+    pub transmission: TexturePtr,
+    /// Index of refraction of the glass, for `transmission`.
+    pub ir: f64,
+}
+
+impl Principled {
+    /// A rough plastic of `base_color`, whose other parameters can be
+    /// changed with struct update syntax.
+    pub fn new(base_color: &TexturePtr) -> Principled {
+        Principled {
+            base_color: Arc::clone(base_color),
+            metallic: Arc::new(SolidColor::new_grey(0.0)),
+            roughness: Arc::new(SolidColor::new_grey(0.5)),
+            specular: Arc::new(SolidColor::new_grey(0.5)),
+            specular_tint: Arc::new(SolidColor::new_grey(0.0)),
+            sheen: Arc::new(SolidColor::new_grey(0.0)),
+            sheen_tint: Arc::new(SolidColor::new_grey(0.5)),
+            clearcoat: Arc::new(SolidColor::new_grey(0.0)),
+            clearcoat_roughness: Arc::new(SolidColor::new_grey(0.03)),
+            transmission: Arc::new(SolidColor::new_grey(0.0)),
+            ir: 1.5,
+        }
+    }
+
+    pub fn new_from_color(base_color: &Color) -> Principled {
+        let texture: TexturePtr = Arc::new(SolidColor::new(*base_color));
+        Principled::new(&texture)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Lobe {
+    Clearcoat,
+    Glass,
+    Specular,
+    Diffuse,
+    Sheen,
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut RenderRng) -> Option<ScatterResult> {
+        let (u, v, p) = (rec.u, rec.v, &rec.p);
+        let scalar = |texture: &TexturePtr| texture.scalar_value(u, v, p).clamp(0.0, 1.0);
+        let base_color = self.base_color.value(u, v, p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let clearcoat = scalar(&self.clearcoat);
+        let transmission = scalar(&self.transmission);
+
+        let uvw = Onb::build_from_w(&rec.normal);
+        let wo = uvw.to_local(&-&vec3::unit_vector(&ray_in.direction));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        // The hue of the base color, at the brightness of white.
+        let white = Color::new(1.0, 1.0, 1.0);
+        let luminance = color::luminance(&base_color);
+        let tint = if luminance > 0.0 {
+            &base_color / luminance
+        } else {
+            white
+        };
+        let dielectric_f0 =
+            0.08 * scalar(&self.specular) * lerp(&white, &tint, scalar(&self.specular_tint));
+        let specular_f0 = lerp(&dielectric_f0, &base_color, metallic);
+
+        // Each layer passes on the light its Fresnel reflectance at the
+        // macrosurface doesn't reflect.
+        let clearcoat_reflectance = clearcoat * schlick(CLEARCOAT_F0, wo.z());
+        let base = 1.0 - clearcoat_reflectance;
+        let glass = base * (1.0 - metallic) * transmission;
+        let opaque = base * (1.0 - (1.0 - metallic) * transmission);
+        let dielectric_reflectance = microfacet::fresnel_schlick(&dielectric_f0, wo.z());
+        let diffuse_color = (1.0 - metallic) * (white - dielectric_reflectance) * base_color;
+        let sheen_color =
+            (1.0 - metallic) * scalar(&self.sheen) * lerp(&white, &tint, scalar(&self.sheen_tint));
+
+        // Pick a lobe by roughly how much light it reflects.
+        let specular_reflectance = microfacet::fresnel_schlick(&specular_f0, wo.z());
+        let lobes = [
+            (Lobe::Clearcoat, clearcoat_reflectance),
+            (Lobe::Glass, glass * luminance),
+            (
+                Lobe::Specular,
+                opaque * color::luminance(&specular_reflectance),
+            ),
+            (Lobe::Diffuse, opaque * color::luminance(&diffuse_color)),
+            (Lobe::Sheen, opaque * color::luminance(&sheen_color)),
+        ];
+        let total: f64 = lobes.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut choice = rng.gen::<f64>() * total;
+        let (mut lobe, mut weight) = lobes[0];
+        for (candidate, candidate_weight) in lobes {
+            if candidate_weight > 0.0 {
+                (lobe, weight) = (candidate, candidate_weight);
+                if choice < candidate_weight {
+                    break;
+                }
+                choice -= candidate_weight;
+            }
+        }
+        let probability = weight / total;
+
+        let (attenuation, wi) = match lobe {
+            Lobe::Clearcoat => {
+                let ggx = Ggx::from_roughness(scalar(&self.clearcoat_roughness));
+                let m = ggx.sample_visible_normal(&wo, rng);
+                let wi = vec3::reflect(&-&wo, &m);
+                let reflectance = clearcoat * schlick(CLEARCOAT_F0, wo.dot(&m));
+                let weight = reflectance * ggx.g2(&wo, &wi) / ggx.g1(&wo);
+                (Color::new(weight, weight, weight), wi)
+            }
+            Lobe::Glass => {
+                let ggx = Ggx::from_roughness(roughness);
+                let eta = if rec.front_face {
+                    self.ir
+                } else {
+                    1.0 / self.ir
+                };
+                let m = ggx.sample_visible_normal(&wo, rng);
+                let wi = if rng.gen::<f64>() < microfacet::fresnel_dielectric(wo.dot(&m), eta) {
+                    vec3::reflect(&-&wo, &m)
+                } else {
+                    vec3::refract(&-&wo, &m, 1.0 / eta)
+                };
+                if (wi.dot(&m) > 0.0) != (wi.z() > 0.0) {
+                    return None;
+                }
+                let weight = glass * ggx.g2(&wo, &wi) / ggx.g1(&wo);
+                (weight * base_color, wi)
+            }
+            Lobe::Specular => {
+                let ggx = Ggx::from_roughness(roughness);
+                let m = ggx.sample_visible_normal(&wo, rng);
+                let wi = vec3::reflect(&-&wo, &m);
+                let reflectance = microfacet::fresnel_schlick(&specular_f0, wo.dot(&m));
+                let weight = opaque * ggx.g2(&wo, &wi) / ggx.g1(&wo);
+                (weight * reflectance, wi)
+            }
+            Lobe::Diffuse => {
+                // Diffuse light is sampled like a Lambertian surface's, and
+                // so can share its samples with the lights.
+                let pdf = CosinePdf::new(&rec.normal);
+                return Some(ScatterResult {
+                    attenuation: (opaque / probability) * diffuse_color,
+                    scattered: Ray::new(rec.p, pdf.generate(rng), ray_in.time),
+                    pdf: Some(Box::new(pdf)),
+                });
+            }
+            Lobe::Sheen => {
+                let wi = vec3::random_cosine_direction(rng);
+                let half = vec3::unit_vector(&(wo + wi));
+                let weight = opaque * PI * (1.0 - wi.dot(&half)).powi(5);
+                (weight * sheen_color, wi)
+            }
+        };
+        if wi.z() <= 0.0 && !matches!(lobe, Lobe::Glass) {
+            return None;
+        }
+
+        Some(ScatterResult {
+            attenuation: &attenuation / probability,
+            scattered: Ray::new(rec.p, uvw.local(&wi), ray_in.time),
+            pdf: None,
+        })
+    }
+
+    /// Density of the diffuse lobe, the only one sampled along with the
+    /// lights.
+    fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal.dot(&vec3::unit_vector(&scattered.direction));
+        cosine.max(0.0) / PI
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Principled",
+            vec![Parameter::number("Index of refraction", self.ir, 0.01)],
+            vec![
+                inspector::texture("Base color", &self.base_color),
+                inspector::texture("Metallic", &self.metallic),
+                inspector::texture("Roughness", &self.roughness),
+                inspector::texture("Specular", &self.specular),
+                inspector::texture("Specular tint", &self.specular_tint),
+                inspector::texture("Sheen", &self.sheen),
+                inspector::texture("Sheen tint", &self.sheen_tint),
+                inspector::texture("Clearcoat", &self.clearcoat),
+                inspector::texture("Clearcoat roughness", &self.clearcoat_roughness),
+                inspector::texture("Transmission", &self.transmission),
+            ],
+            |values, parts| {
+                Part::Material(Arc::new(Principled {
+                    base_color: parts[0].texture(),
+                    metallic: parts[1].texture(),
+                    roughness: parts[2].texture(),
+                    specular: parts[3].texture(),
+                    specular_tint: parts[4].texture(),
+                    sheen: parts[5].texture(),
+                    sheen_tint: parts[6].texture(),
+                    clearcoat: parts[7].texture(),
+                    clearcoat_roughness: parts[8].texture(),
+                    transmission: parts[9].texture(),
+                    ir: values[0].number(),
+                }))
+            },
+        ))
+    }
+}
+
+fn lerp(a: &Color, b: &Color, t: f64) -> Color {
+    *a + t * (*b - *a)
+}
+
+/// Schlick's approximation of the Fresnel reflectance of a dielectric
+/// reflecting `f0` at normal incidence.
+fn schlick(f0: f64, cos_i: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cos_i.clamp(0.0, 1.0)).powi(5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::test_util::{normal_incidence_hit, TEST_SAMPLES};
+
+    /// Mean weight of the light the material scatters at normal incidence,
+    /// its albedo.
+    fn albedo(material: &Principled) -> Color {
+        let (ray, rec, mut rng) = normal_incidence_hit();
+        let mut sum = Color::origin();
+        for _ in 0..TEST_SAMPLES {
+            if let Some(result) = material.scatter(&ray, &rec, &mut rng) {
+                sum += result.attenuation;
+            }
+        }
+        &sum / TEST_SAMPLES as f64
+    }
+
+    #[test]
+    fn test_principled() {
+        // A smooth metal reflects its base color.
+        let gold_color = Color::new(1.0, 0.8, 0.3);
+        let metal = Principled {
+            metallic: Arc::new(SolidColor::new_grey(1.0)),
+            roughness: Arc::new(SolidColor::new_grey(0.0)),
+            ..Principled::new_from_color(&gold_color)
+        };
+        assert!((albedo(&metal) - gold_color).length() < 1e-3);
+
+        // A white dielectric reflects no more light than it receives, even
+        // with every layer.
+        let plastic = Principled {
+            sheen: Arc::new(SolidColor::new_grey(1.0)),
+            clearcoat: Arc::new(SolidColor::new_grey(1.0)),
+            ..Principled::new_from_color(&Color::new(1.0, 1.0, 1.0))
+        };
+        let white = albedo(&plastic);
+        assert!(white.x() <= 1.02 && white.x() > 0.8, "{white}");
+
+        // Clear glass lets all light through, some reflected.
+        let glass = Principled {
+            transmission: Arc::new(SolidColor::new_grey(1.0)),
+            roughness: Arc::new(SolidColor::new_grey(0.0)),
+            ..Principled::new_from_color(&Color::new(1.0, 1.0, 1.0))
+        };
+        assert!((albedo(&glass).x() - 1.0).abs() < 1e-3);
+    }
+}
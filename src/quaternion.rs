@@ -0,0 +1,163 @@
+use std::ops;
+
+use crate::{
+    matrix::Matrix4,
+    vec3::{unit_vector, Vec3},
+};
+
+/// Unit quaternion representing a rotation.
+#[derive(Debug, Clone, Copy)]
+pub struct Quaternion {
+    w: f64,
+    v: Vec3,
+}
+
+impl Quaternion {
+    pub fn identity() -> Quaternion {
+        Quaternion {
+            w: 1.0,
+            v: Vec3::origin(),
+        }
+    }
+
+    /// Rotation by `angle` degrees about `axis`, matching
+    /// [`Matrix4::rotation`].
+    pub fn from_axis_angle(axis: &Vec3, angle: f64) -> Quaternion {
+        let half = angle.to_radians() / 2.0;
+        Quaternion {
+            w: half.cos(),
+            v: half.sin() * unit_vector(axis),
+        }
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.v.dot(&other.v)
+    }
+
+    /// Angle in radians of the rotation taking `self` to `other`.
+    pub fn angle_to(&self, other: &Quaternion) -> f64 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    /// Spherical linear interpolation, rotating at a constant rate along the
+    /// shorter arc from `self` at `t = 0` to `other` at `t = 1`.
+    pub fn slerp(&self, other: &Quaternion, t: f64) -> Quaternion {
+        // `q` and `-q` are the same rotation; pick the one nearer to `self`.
+        let (other, cos_theta) = match self.dot(other) {
+            d if d < 0.0 => (other.scale(-1.0), -d),
+            d => (*other, d),
+        };
+
+        // Nearly parallel, where the sine below would be close to zero.
+        if cos_theta > 0.9995 {
+            return (self.scale(1.0 - t) + other.scale(t)).normalized();
+        }
+
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        let a = ((1.0 - t) * theta).sin() / sin_theta;
+        let b = (t * theta).sin() / sin_theta;
+        self.scale(a) + other.scale(b)
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            v: -&self.v,
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let Quaternion { w, v } = *self;
+        let (x, y, z) = (v.x(), v.y(), v.z());
+        Matrix4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    fn scale(&self, s: f64) -> Quaternion {
+        Quaternion {
+            w: s * self.w,
+            v: s * self.v,
+        }
+    }
+
+    fn normalized(&self) -> Quaternion {
+        self.scale(1.0 / self.dot(self).sqrt())
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::identity()
+    }
+}
+
+impl ops::Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w + rhs.w,
+            v: self.v + rhs.v,
+        }
+    }
+}
+
+/// Composition: `a * b` rotates by `b` first.
+impl ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * rhs.w - self.v.dot(&rhs.v),
+            v: self.w * rhs.v + rhs.w * self.v + self.v.cross(&rhs.v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slerp() {
+        let axis = Vec3::new(1.0, 2.0, -0.5);
+        let start = Quaternion::from_axis_angle(&axis, 10.0);
+        let end = Quaternion::from_axis_angle(&axis, 170.0);
+
+        let halfway = start.slerp(&end, 0.5);
+        let expected = Matrix4::rotation(&axis, 90.0);
+        let p = Vec3::new(0.3, -1.0, 2.0);
+        let difference = halfway.to_matrix().transform_vector(&p) - expected.transform_vector(&p);
+        assert!(difference.length() < 1e-9);
+        assert!((start.angle_to(&halfway) - 80f64.to_radians()).abs() < 1e-9);
+
+        let composed = Quaternion::from_axis_angle(&axis, 30.0) * start;
+        assert!((composed.angle_to(&Quaternion::from_axis_angle(&axis, 40.0))).abs() < 1e-6);
+
+        // Takes the short way round from 350 to 10 degrees.
+        let start = Quaternion::from_axis_angle(&axis, 350.0);
+        let end = Quaternion::from_axis_angle(&axis, 10.0);
+        let halfway = start.slerp(&end, 0.5);
+        assert!(halfway.angle_to(&Quaternion::identity()) < 1e-6);
+    }
+}
@@ -2,51 +2,288 @@ use std::{
     error::Error,
     io::{self, Write},
     ops::Range,
-    path::{Path, PathBuf},
+    path::PathBuf,
     sync::{
+        atomic::{AtomicU8, Ordering},
         mpsc::{channel, Receiver, RecvError, Sender},
-        Arc,
+        Arc, Condvar, Mutex,
     },
-    time::Instant,
+    time::{Duration, Instant},
     vec,
 };
 
-use image::Rgb;
+use image::{ImageBuffer, Luma, Rgb, Rgb32FImage};
 use rand::Rng;
 
 use crate::{
-    bvh::BVHNode,
+    bvh::Bvh,
+    camera::Camera,
+    checkpoint::{self, Checkpoint},
     color::{self, Background},
     hittable::{HitRecord, Hittable},
-    material::ScatterResult,
+    hittable_list::HittableList,
+    material::{self, ScatterResult},
+    output,
+    pdf::{HittablePdf, MixturePdf, Pdf},
     ray::Ray,
-    scenes::{Scene, SceneConfig},
+    rng::{self, RenderRng},
+    scenes::SceneConfig,
+    tiles::{self, Tile, TileOrder, TileQueue, TILE_SIZE},
+    tone_map::ToneMapping,
     vec3::Color,
 };
 
+#[derive(Clone)]
 pub struct RenderConfig {
     pub file_name: PathBuf,
     pub scene: SceneConfig,
+    /// Used when saving to an 8-bit format.
+    pub tone_mapping: ToneMapping,
+    /// Where to save an image of the number of samples taken per pixel.
+    pub heatmap_file: Option<PathBuf>,
+    pub threads: usize,
+    pub tile_order: TileOrder,
+    /// Where to save checkpoints of the render, every `checkpoint_interval`
+    /// and once it is done.
+    pub checkpoint_file: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    /// [`checkpoint::scene_hash`] of the scene, saved in checkpoints.
+    pub scene_hash: u64,
+    /// Checkpoint to add the samples to, instead of starting from scratch.
+    pub resume_from: Option<Checkpoint>,
 }
 
 impl RenderConfig {
-    pub fn new(file_name: PathBuf, scene: Scene) -> RenderConfig {
-        let scene = SceneConfig::get_scene(&scene);
-        RenderConfig { file_name, scene }
+    pub fn new(file_name: PathBuf, scene: SceneConfig) -> RenderConfig {
+        RenderConfig {
+            file_name,
+            scene,
+            tone_mapping: ToneMapping::default(),
+            heatmap_file: None,
+            threads: default_threads(),
+            tile_order: TileOrder::default(),
+            checkpoint_file: None,
+            checkpoint_interval: checkpoint::DEFAULT_INTERVAL,
+            scene_hash: 0,
+            resume_from: None,
+        }
+    }
+}
+
+/// Position, averaged color and number of samples taken.
+type Pixel = (u32, u32, Rgb<f32>, u32);
+
+/// Add the samples of `pixel` to those already averaged in `image`, keeping
+/// count of them in `sample_counts`.
+fn accumulate(image: &mut Rgb32FImage, sample_counts: &mut SampleCounts, pixel: &Pixel) {
+    let (x, y, color, samples) = *pixel;
+    if samples == 0 {
+        return;
+    }
+    let Luma([previous]) = *sample_counts.get_pixel(x, y);
+    let total = previous + samples;
+    let weight = samples as f32 / total as f32;
+    let mean = image.get_pixel_mut(x, y);
+    for (mean, color) in mean.0.iter_mut().zip(color.0) {
+        *mean += weight * (color - *mean);
+    }
+    sample_counts.put_pixel(x, y, Luma([total]));
+}
+
+/// Ranges of sample indices taken by each pass of a progressive render:
+/// `0..1`, `1..2`, `2..4` and so on, doubling the samples per pixel with
+/// every pass up to `samples_per_pixel`.
+fn progressive_passes(samples_per_pixel: u32) -> Vec<Range<u32>> {
+    let mut passes = Vec::new();
+    let mut start = 0;
+    while start < samples_per_pixel {
+        let end = (2 * start).clamp(1, samples_per_pixel);
+        passes.push(start..end);
+        start = end;
+    }
+    passes
+}
+
+/// Progress reported by the render threads.
+enum RenderMessage {
+    TileStarted(Tile),
+    TileFinished(Tile, Vec<Pixel>),
+}
+
+/// Number of samples taken for every pixel of a render.
+pub type SampleCounts = ImageBuffer<Luma<u32>, Vec<u32>>;
+
+/// Luminance below which pixels count as black when estimating their
+/// relative error, so that dark pixels don't sample forever.
+const MIN_ADAPTIVE_LUMINANCE: f64 = 1e-3;
+
+/// Settings for sampling each pixel only until its estimated error is below a
+/// threshold, instead of always taking `samples_per_pixel` samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Samples taken before the error is first estimated.
+    pub min_samples: u32,
+    /// Largest acceptable standard error of a pixel's mean luminance, relative
+    /// to that mean.
+    pub threshold: f64,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: u32, threshold: f64) -> AdaptiveSampling {
+        AdaptiveSampling {
+            min_samples,
+            threshold,
+        }
+    }
+
+    /// Call `sample` with the index of each sample until the relative error
+    /// of the running mean luminance drops below the threshold or
+    /// `max_samples` have been taken. Returns the sum of the samples and their
+    /// number.
+    pub fn sample_pixel(
+        &self,
+        max_samples: u32,
+        mut sample: impl FnMut(u32) -> Color,
+    ) -> (Color, u32) {
+        let min_samples = self.min_samples.max(2);
+        let mut sum = Color::origin();
+        let mut count = 0;
+        // Welford's running mean and sum of squared deviations.
+        let mut mean = 0.0;
+        let mut m2 = 0.0;
+
+        while count < max_samples {
+            let color = sample(count);
+            sum += color;
+            count += 1;
+
+            let luminance = color::luminance(&color);
+            let delta = luminance - mean;
+            mean += delta / count as f64;
+            m2 += delta * (luminance - mean);
+
+            if count >= min_samples {
+                let variance = m2 / (count - 1) as f64;
+                let standard_error = (variance / count as f64).sqrt();
+                if standard_error <= self.threshold * mean.max(MIN_ADAPTIVE_LUMINANCE) {
+                    break;
+                }
+            }
+        }
+
+        (sum, count)
+    }
+}
+
+/// A finished render, or the part of it done so far.
+#[derive(Clone)]
+pub struct RenderOutput {
+    /// Linear, unclamped colors.
+    pub image: Rgb32FImage,
+    pub sample_counts: SampleCounts,
+}
+
+impl RenderOutput {
+    pub fn average_samples_per_pixel(&self) -> f64 {
+        let total: u64 = self.sample_counts.iter().map(|&count| count as u64).sum();
+        total as f64 / self.sample_counts.len().max(1) as f64
     }
 }
 
-type Pixel = (u32, u32, Rgb<u8>);
+const RUNNING: u8 = 0;
+const PAUSED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// Handle for pausing, resuming or cancelling a render from another thread.
+/// Clones control the same render.
+#[derive(Clone, Default)]
+pub struct RenderControl {
+    inner: Arc<ControlInner>,
+}
+
+#[derive(Default)]
+struct ControlInner {
+    state: AtomicU8,
+    /// Paused threads wait on `resumed`; the mutex only guards the wait.
+    lock: Mutex<()>,
+    resumed: Condvar,
+}
+
+impl RenderControl {
+    pub fn pause(&self) {
+        let _ =
+            self.inner
+                .state
+                .compare_exchange(RUNNING, PAUSED, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        if self
+            .inner
+            .state
+            .compare_exchange(PAUSED, RUNNING, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            self.wake();
+        }
+    }
+
+    /// Stop the render for good. Threads finish the pixel they are on and
+    /// exit without reporting it.
+    pub fn cancel(&self) {
+        self.inner.state.store(CANCELLED, Ordering::SeqCst);
+        self.wake();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.state.load(Ordering::SeqCst) == PAUSED
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.state.load(Ordering::SeqCst) == CANCELLED
+    }
+
+    /// Block while the render is paused. Returns whether to carry on, i.e.
+    /// `false` once cancelled.
+    fn proceed(&self) -> bool {
+        if self.inner.state.load(Ordering::Relaxed) == RUNNING {
+            return true;
+        }
+        let mut guard = self.inner.lock.lock().unwrap();
+        while self.is_paused() {
+            guard = self.inner.resumed.wait(guard).unwrap();
+        }
+        !self.is_cancelled()
+    }
+
+    fn wake(&self) {
+        // Taking the lock orders the state change before any thread that is
+        // about to wait, so none misses the notification.
+        drop(self.inner.lock.lock().unwrap());
+        self.inner.resumed.notify_all();
+    }
+}
 
 pub struct ThreadedRenderer {
     image_width: usize,
     image_height: usize,
+    image: Rgb32FImage,
+    sample_counts: SampleCounts,
+    /// `image` converted to 8-bit RGBA for display.
     pixels: Box<[u8]>,
     threadpool: Option<threadpool::ThreadPool>,
-    sender: Sender<Pixel>,
-    receiver: Receiver<Pixel>,
-    pixel_counter: usize,
+    sender: Sender<RenderMessage>,
+    receiver: Receiver<RenderMessage>,
+    tiles_remaining: usize,
+    /// Tiles being rendered right now.
+    tiles_in_flight: Vec<Tile>,
+    control: RenderControl,
+    tone_mapping: ToneMapping,
     pub threads_to_use: usize,
+    pub tile_order: TileOrder,
+    /// Render the whole image in passes of doubling samples per pixel,
+    /// showing a noisy image early that sharpens with every pass.
+    pub progressive: bool,
 }
 
 impl Default for ThreadedRenderer {
@@ -55,12 +292,19 @@ impl Default for ThreadedRenderer {
         Self {
             image_width: 0,
             image_height: 0,
+            image: Rgb32FImage::new(0, 0),
+            sample_counts: SampleCounts::new(0, 0),
             pixels: Box::new([]),
             threadpool: None,
             sender,
             receiver,
-            pixel_counter: 0,
+            tiles_remaining: 0,
+            tiles_in_flight: Vec::new(),
+            control: RenderControl::default(),
+            tone_mapping: ToneMapping::default(),
             threads_to_use: 1,
+            tile_order: TileOrder::default(),
+            progressive: false,
         }
     }
 }
@@ -71,40 +315,88 @@ impl ThreadedRenderer {
             eprintln!("Render already in progress...");
             return;
         }
-        self.pixel_counter = 0;
         let (width, height) = scene.image_size();
         self.init_pixels(width as _, height as _);
 
+        self.control = RenderControl::default();
+
         let num_threads = self.threads_to_use.clamp(1, num_cpus::get());
         let pool = threadpool::ThreadPool::new(num_threads);
-        render_with_threadpool(&pool, &self.sender, &scene);
+        self.tiles_remaining = render_with_threadpool(
+            &pool,
+            &self.sender,
+            &scene,
+            scene_bvh(&scene),
+            self.tile_order,
+            self.progressive,
+            None,
+            &self.control,
+        );
         self.threadpool = Some(pool);
     }
 
+    pub fn pause(&self) {
+        self.control.pause();
+    }
+
+    pub fn resume(&self) {
+        self.control.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_render_in_progress() && self.control.is_paused()
+    }
+
+    /// Stop the render in progress, keeping the tiles finished so far, and
+    /// return to idle.
+    pub fn cancel(&mut self) {
+        if self.threadpool.take().is_none() {
+            return;
+        }
+        self.control.cancel();
+        while let Ok(message) = self.receiver.try_recv() {
+            if let RenderMessage::TileFinished(_, pixels) = message {
+                for pixel in &pixels {
+                    self.set_pixel(pixel);
+                }
+            }
+        }
+        // Threads still finishing a pixel exit when they find the receiver
+        // gone, instead of reporting to the next render.
+        (self.sender, self.receiver) = channel();
+        self.tiles_in_flight.clear();
+        self.tiles_remaining = 0;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.control.is_cancelled()
+    }
+
     pub fn check_progress(&mut self) -> bool {
-        if self.threadpool.is_some() && self.is_render_finished() {
+        if self.threadpool.is_some() && self.tiles_remaining == 0 {
             self.threadpool.take();
         }
 
-        let mut new_pixels: Vec<Pixel> = Vec::new();
-
-        loop {
-            match self.receiver.try_recv() {
-                Ok(pixel) => new_pixels.push(pixel),
-                Err(_) => {
-                    for pixel in &new_pixels {
+        let mut progress = false;
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                RenderMessage::TileStarted(tile) => self.tiles_in_flight.push(tile),
+                RenderMessage::TileFinished(tile, pixels) => {
+                    for pixel in &pixels {
                         self.set_pixel(pixel);
-                        self.pixel_counter += 1;
                     }
-                    break;
+                    // Progressive passes can have the same tile in flight
+                    // twice, so only one of them is done.
+                    if let Some(index) = self.tiles_in_flight.iter().position(|t| *t == tile) {
+                        self.tiles_in_flight.swap_remove(index);
+                    }
+                    self.tiles_remaining -= 1;
                 }
             }
+            progress = true;
         }
 
-        if new_pixels.is_empty() {
-            return false;
-        }
-        true
+        progress
     }
 
     pub fn get_pixels(&self) -> &[u8] {
@@ -119,6 +411,26 @@ impl ThreadedRenderer {
         [self.image_width, self.image_height]
     }
 
+    pub fn tone_mapping(&self) -> &ToneMapping {
+        &self.tone_mapping
+    }
+
+    /// Change how the display pixels are derived from the linear image,
+    /// updating the pixels rendered so far.
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+        for (x, y, color) in self.image.enumerate_pixels() {
+            let index = x as usize * 4 + (y as usize * self.image_width * 4);
+            let display = tone_mapping.map(*color);
+            self.pixels[index..index + 3].copy_from_slice(&display.0);
+        }
+    }
+
+    /// The linear image rendered so far.
+    pub fn get_float_image(&self) -> &Rgb32FImage {
+        &self.image
+    }
+
     pub fn get_image(&self) -> Option<image::RgbaImage> {
         let mut image_vec = Vec::new();
         image_vec.extend_from_slice(self.pixels.as_ref());
@@ -126,27 +438,54 @@ impl ThreadedRenderer {
         image::RgbaImage::from_vec(self.image_width as _, self.image_height as _, image_vec)
     }
 
+    /// Whether the last render ran to completion.
     pub fn is_render_finished(&self) -> bool {
-        self.pixel_counter >= self.image_height * self.image_width
+        self.tiles_remaining == 0 && !self.is_cancelled()
     }
 
+    pub fn tiles_in_flight(&self) -> &[Tile] {
+        &self.tiles_in_flight
+    }
+
+    /// Prepare for a render of `width` by `height` pixels. An image of the
+    /// same size is kept on display until its pixels are rendered over, so
+    /// that moving the camera doesn't flash black.
     fn init_pixels(&mut self, width: usize, height: usize) {
+        self.sample_counts = SampleCounts::new(width as _, height as _);
+        if [width, height] == self.get_image_size() {
+            return;
+        }
         self.image_width = width;
         self.image_height = height;
+        self.image = Rgb32FImage::new(width as _, height as _);
         let vec_size = width * height * 4;
         self.pixels = vec![0; vec_size].into_boxed_slice();
     }
 
     fn set_pixel(&mut self, pixel: &Pixel) {
-        let index = pixel.0 as usize * 4 + (pixel.1 as usize * self.image_width * 4);
+        accumulate(&mut self.image, &mut self.sample_counts, pixel);
+        let (x, y, _, _) = *pixel;
+        let color = *self.image.get_pixel(x, y);
+
+        let index = x as usize * 4 + (y as usize * self.image_width * 4);
+        let display = self.tone_mapping.map(color);
         for i in 0..3 {
-            self.pixels[index + i] = pixel.2[i];
+            self.pixels[index + i] = display[i];
         }
         self.pixels[index + 3] = 0xFF;
     }
 }
 
-fn ray_color(ray: &Ray, background: &Background, world: &impl Hittable, depth: u32) -> Color {
+/// Color of the light coming back along `ray` from `world`, followed through
+/// at most `depth` bounces.
+pub fn ray_color(
+    ray: &Ray,
+    background: &Background,
+    world: &impl Hittable,
+    lights: &HittableList,
+    depth: u32,
+    rng: &mut RenderRng,
+) -> Color {
     // If we've exceeded the ray bounce limit, no more light is gathered.
     if depth == 0 {
         return Color::new(0.0, 0.0, 0.0);
@@ -155,7 +494,7 @@ fn ray_color(ray: &Ray, background: &Background, world: &impl Hittable, depth: u
     let mut hit_record = HitRecord::empty();
 
     // If the ray hits nothing, return the background color.
-    if !world.hit(ray, 0.001, f64::INFINITY, &mut hit_record) {
+    if !world.hit(ray, 0.001, f64::INFINITY, &mut hit_record, rng) {
         return background.background_color(&ray.direction);
     }
 
@@ -163,122 +502,499 @@ fn ray_color(ray: &Ray, background: &Background, world: &impl Hittable, depth: u
         .material
         .as_ref()
         .expect("HitRecord should contain material");
+    let chosen = material::choose(material, &hit_record, rng);
+    let material = chosen.as_ref().unwrap_or(material);
     let emitted = material.emitted(hit_record.u, hit_record.v, &hit_record.p);
 
-    let scatter_result = material.scatter(ray, &hit_record);
+    let scatter_result = match material.scatter(ray, &hit_record, rng) {
+        None => return emitted,
+        Some(scatter_result) => scatter_result,
+    };
+
+    let ScatterResult {
+        attenuation,
+        scattered,
+        pdf,
+    } = scatter_result;
+
+    let material_pdf = match pdf {
+        // Specular scattering has no distribution to sample lights against.
+        None => {
+            return emitted
+                + attenuation * ray_color(&scattered, background, world, lights, depth - 1, rng)
+        }
+        Some(pdf) => pdf,
+    };
 
-    match scatter_result {
-        None => emitted,
-        Some(ScatterResult {
-            attenuation,
-            scattered,
-        }) => emitted + attenuation * ray_color(&scattered, background, world, depth - 1),
+    // Sample the lights and the material evenly, if the scene has lights.
+    let (scattered, pdf_value) = if lights.is_empty() {
+        let pdf_value = material_pdf.value(&scattered.direction);
+        (scattered, pdf_value)
+    } else {
+        let light_pdf = HittablePdf::new(lights, &hit_record.p, ray.time);
+        let mixture_pdf = MixturePdf::new(&light_pdf, material_pdf.as_ref());
+        let scattered = Ray::new(hit_record.p, mixture_pdf.generate(rng), ray.time);
+        let pdf_value = mixture_pdf.value(&scattered.direction);
+        (scattered, pdf_value)
+    };
+
+    if pdf_value <= 0.0 {
+        return emitted;
     }
+
+    let scattering_pdf = material.scattering_pdf(ray, &hit_record, &scattered);
+    emitted
+        + (scattering_pdf / pdf_value)
+            * attenuation
+            * ray_color(&scattered, background, world, lights, depth - 1, rng)
 }
 
-fn render(scene: SceneConfig) -> Result<image::RgbImage, RecvError> {
-    let (image_width, image_height) = scene.image_size();
+/// Render `scene` into an image of linear, unclamped colors, blocking until
+/// every pixel is done.
+pub fn render(scene: &SceneConfig) -> Result<Rgb32FImage, RecvError> {
+    let output = render_with_progress(
+        scene,
+        default_threads(),
+        TileOrder::default(),
+        None,
+        |_, _| {},
+    )?;
+    Ok(output.image)
+}
 
-    let mut image = image::RgbImage::new(image_width, image_height);
+/// Threads to render with by default: all but one of the CPUs, to keep the
+/// machine responsive, but at least one.
+pub fn default_threads() -> usize {
+    num_cpus::get().saturating_sub(1).max(1)
+}
+
+/// Like [`render`] on `threads` threads working through the image's tiles in
+/// `tile_order`, also returning the number of samples taken per pixel.
+///
+/// Pixels of `resume_from` take only the samples they lack, or none if they
+/// have any and the scene samples adaptively. `on_progress` is called with
+/// the number of tiles still to go and the image so far whenever a tile is
+/// done.
+pub fn render_with_progress(
+    scene: &SceneConfig,
+    threads: usize,
+    tile_order: TileOrder,
+    resume_from: Option<RenderOutput>,
+    on_progress: impl FnMut(usize, &RenderOutput),
+) -> Result<RenderOutput, RecvError> {
+    render_world_with_progress(
+        scene,
+        scene_bvh(scene),
+        threads,
+        tile_order,
+        resume_from,
+        on_progress,
+    )
+}
 
-    let pool = threadpool::ThreadPool::new(num_cpus::get() - 1);
+/// Like [`render_with_progress`] with `world` already built from `scene`'s
+/// objects by [`scene_bvh`].
+fn render_world_with_progress(
+    scene: &SceneConfig,
+    world: Bvh,
+    threads: usize,
+    tile_order: TileOrder,
+    resume_from: Option<RenderOutput>,
+    mut on_progress: impl FnMut(usize, &RenderOutput),
+) -> Result<RenderOutput, RecvError> {
+    let (image_width, image_height) = scene.image_size();
+    let mut output = resume_from.unwrap_or_else(|| RenderOutput {
+        image: Rgb32FImage::new(image_width, image_height),
+        sample_counts: SampleCounts::new(image_width, image_height),
+    });
+
+    let pool = threadpool::ThreadPool::new(threads.max(1));
     let (tx, rx) = channel();
 
-    let start = Instant::now();
+    let tile_count = render_with_threadpool(
+        &pool,
+        &tx,
+        scene,
+        world,
+        tile_order,
+        false,
+        Some(&output.sample_counts),
+        &RenderControl::default(),
+    );
+
+    let mut tiles_remaining = tile_count;
+    while tiles_remaining > 0 {
+        if let RenderMessage::TileFinished(_, pixels) = rx.recv()? {
+            for pixel in &pixels {
+                accumulate(&mut output.image, &mut output.sample_counts, pixel);
+            }
+            tiles_remaining -= 1;
+            on_progress(tiles_remaining, &output);
+        }
+    }
 
-    render_with_threadpool(&pool, &tx, &scene);
+    Ok(output)
+}
 
-    let mut lines_done = 0;
-    for i in 0..(image_width * image_height) {
-        let (x, y, pixel) = rx.recv()?;
-        image.put_pixel(x, y, pixel);
-        if i % image_width == 0 {
-            lines_done += 1;
-            let lines_remaining = image_height - lines_done;
-            eprint!("\rScanlines remaining: {lines_remaining} ");
-            io::stderr().flush().unwrap();
+/// BVH of the scene's objects, bounding them while the shutter is open.
+fn scene_bvh(scene: &SceneConfig) -> Bvh {
+    Bvh::new(
+        &scene.world.objects,
+        scene.camera.shutter_open_time(),
+        scene.camera.shutter_close_time(),
+    )
+}
+
+/// A render in progress, shared by its threads.
+struct RenderJob {
+    world: Bvh,
+    lights: HittableList,
+    camera: Camera,
+    background: Background,
+    image_width: u32,
+    image_height: u32,
+    /// Sample indices taken by each pass over the image. A progressive render
+    /// has several passes and no adaptive sampling, which needs all of a
+    /// pixel's samples at once.
+    passes: Vec<Range<u32>>,
+    adaptive_sampling: Option<AdaptiveSampling>,
+    max_depth: u32,
+    seed: u64,
+    deadline: Option<Instant>,
+    /// Samples per pixel taken before the render was resumed.
+    samples_taken: Option<SampleCounts>,
+    tiles: TileQueue,
+    control: RenderControl,
+}
+
+impl RenderJob {
+    /// Render the pixels of `tile` for `pass`, or `None` if the render is
+    /// cancelled before they are all done.
+    fn render_tile(&self, pass: usize, tile: &Tile) -> Option<Vec<Pixel>> {
+        let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+        for y in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                if !self.control.proceed() {
+                    return None;
+                }
+                pixels.push(self.render_pixel(x, y, self.samples_to_take(x, y, pass)));
+            }
         }
+        Some(pixels)
     }
 
-    let duration = start.elapsed();
-    eprint!("\nDone.\nTime elapsed while rendering: {:?}", duration);
+    /// Indices of the samples of pixel `x`, `y` for `pass` that weren't taken
+    /// before resuming. Adaptive sampling decides on all of a pixel's samples
+    /// at once, so it takes none for pixels that have any.
+    fn samples_to_take(&self, x: u32, y: u32, pass: usize) -> Range<u32> {
+        let samples = self.passes[pass].clone();
+        let taken = match &self.samples_taken {
+            Some(samples_taken) => samples_taken.get_pixel(x, y).0[0],
+            None => return samples,
+        };
+        if self.adaptive_sampling.is_some() && taken > 0 {
+            return 0..0;
+        }
+        samples.start.max(taken)..samples.end
+    }
 
-    Ok(image)
+    /// Take the samples with indices in `samples`, or just the first of them
+    /// once past the deadline.
+    fn render_pixel(&self, x: u32, y: u32, samples: Range<u32>) -> Pixel {
+        if samples.is_empty() {
+            return (x, y, Rgb([0.0; 3]), 0);
+        }
+        let samples = match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                samples.start..samples.end.min(samples.start + 1)
+            }
+            _ => samples,
+        };
+        // The camera's v runs up the image.
+        let j = self.image_height - 1 - y;
+        let sample = |sample_index| {
+            let mut rng = rng::sample_rng(self.seed, x, y, sample_index);
+            let u_rand: f64 = rng.gen();
+            let v_rand: f64 = rng.gen();
+            let u: f64 = (x as f64 + u_rand) / (self.image_width - 1) as f64;
+            let v: f64 = (j as f64 + v_rand) / (self.image_height - 1) as f64;
+            let ray = self.camera.get_ray(u, v, &mut rng);
+            ray_color(
+                &ray,
+                &self.background,
+                &self.world,
+                &self.lights,
+                self.max_depth,
+                &mut rng,
+            )
+        };
+        let (pixel_color, samples) = match &self.adaptive_sampling {
+            Some(adaptive) => adaptive.sample_pixel(samples.end, sample),
+            None => {
+                let mut pixel_color = Color::origin();
+                for sample_index in samples.clone() {
+                    pixel_color += sample(sample_index);
+                }
+                (pixel_color, samples.len() as u32)
+            }
+        };
+        (x, y, color::color_to_float(pixel_color, samples), samples)
+    }
 }
 
-fn render_with_threadpool(pool: &threadpool::ThreadPool, tx: &Sender<Pixel>, scene: &SceneConfig) {
+/// Start rendering `scene`, whose objects are in `world`, on every thread of
+/// `pool`, in passes of doubling
+/// samples per pixel if `progressive` and skipping the `samples_taken`
+/// already. Returns the number of tiles, over all passes, that will be
+/// reported as finished through `tx` unless the render is cancelled through
+/// `control` or the receiver is dropped.
+#[allow(clippy::too_many_arguments)]
+fn render_with_threadpool(
+    pool: &threadpool::ThreadPool,
+    tx: &Sender<RenderMessage>,
+    scene: &SceneConfig,
+    world: Bvh,
+    tile_order: TileOrder,
+    progressive: bool,
+    samples_taken: Option<&SampleCounts>,
+    control: &RenderControl,
+) -> usize {
     let (image_width, image_height) = scene.image_size();
-    let bvh = BVHNode::new(&scene.world.objects, 0.0, 1.0);
-    let world_arc = Arc::new(bvh);
-    let camera_arc = Arc::new(scene.camera);
-    let samples_per_pixel = scene.samples_per_pixel;
-    let max_depth = scene.max_depth;
-    let background = scene.background;
-
-    let threads = pool.max_count();
-    let ranges = divide_into_ranges(image_height, threads as _);
-    for range in ranges {
+    let (passes, adaptive_sampling) = if progressive {
+        (progressive_passes(scene.samples_per_pixel), None)
+    } else {
+        let all_samples = 0..scene.samples_per_pixel;
+        (vec![all_samples], scene.adaptive_sampling)
+    };
+    let pass_count = passes.len();
+    let job = Arc::new(RenderJob {
+        world,
+        lights: scene.lights.clone(),
+        camera: scene.camera,
+        background: scene.background,
+        image_width,
+        image_height,
+        passes,
+        adaptive_sampling,
+        max_depth: scene.max_depth,
+        seed: scene.seed,
+        deadline: scene.time_limit.map(|limit| Instant::now() + limit),
+        samples_taken: samples_taken.cloned(),
+        tiles: TileQueue::new(
+            tiles::tiles(image_width, image_height, TILE_SIZE, tile_order),
+            pass_count,
+        ),
+        control: control.clone(),
+    });
+
+    for _ in 0..pool.max_count().min(job.tiles.len()) {
         let tx = tx.clone();
-        let thread_world = Arc::clone(&world_arc);
-        let thread_camera = Arc::clone(&camera_arc);
+        let job = Arc::clone(&job);
         pool.execute(move || {
-            for j in range.rev() {
-                let mut rng = rand::thread_rng();
-                for i in 0..image_width {
-                    let mut pixel_color = Color::origin();
-                    for _ in 0..samples_per_pixel {
-                        let u_rand: f64 = rng.gen();
-                        let v_rand: f64 = rng.gen();
-                        let u: f64 = (i as f64 + u_rand) / (image_width - 1) as f64;
-                        let v: f64 = (j as f64 + v_rand) / (image_height - 1) as f64;
-                        let ray = thread_camera.get_ray(u, v);
-                        pixel_color +=
-                            ray_color(&ray, &background, thread_world.as_ref(), max_depth);
-                    }
-                    let pixel = color::color_to_pixel(pixel_color, samples_per_pixel);
-                    let y = image_height - 1 - j;
-                    tx.send((i, y, pixel)).expect("Could not send data!");
+            while let Some((pass, tile)) = job.tiles.pop() {
+                if tx.send(RenderMessage::TileStarted(tile)).is_err() {
+                    return;
+                }
+                let Some(pixels) = job.render_tile(pass, &tile) else {
+                    return;
+                };
+                if tx.send(RenderMessage::TileFinished(tile, pixels)).is_err() {
+                    return;
                 }
             }
         });
     }
+
+    job.tiles.len()
 }
 
-fn divide_into_ranges(rows: u32, ranges: u32) -> Vec<Range<u32>> {
-    let mut results: Vec<Range<u32>> = Vec::new();
-    if rows == 0 {
-        results.push(0..0);
-    } else if rows <= ranges {
-        for i in 0..rows {
-            results.push(i..(i + 1));
+/// Render the scene, reporting progress on stderr, and save it to the
+/// configured file.
+pub fn render_and_save(config: RenderConfig) -> Result<(), Box<dyn Error>> {
+    let scene = &config.scene;
+    let world = scene_bvh(scene);
+    eprintln!("BVH: {}", world.stats());
+
+    let resume_from = config.resume_from.map(|checkpoint| checkpoint.render);
+    if let Some(resume_from) = &resume_from {
+        eprintln!(
+            "Resuming with {:.1} samples per pixel taken",
+            resume_from.average_samples_per_pixel()
+        );
+    }
+    let save_checkpoint = |output: &RenderOutput| {
+        let Some(checkpoint_file) = &config.checkpoint_file else {
+            return;
+        };
+        let checkpoint = Checkpoint {
+            scene_hash: config.scene_hash,
+            render: output.clone(),
+        };
+        // A failed checkpoint shouldn't cost the render itself.
+        if let Err(e) = checkpoint.save(checkpoint_file) {
+            eprintln!("\nCould not save checkpoint: {e}");
         }
-    } else {
-        let group_size = rows / ranges;
-        let mut remain = rows % ranges;
-        let mut start;
-        let mut end = 0;
-        for _ in 0..ranges {
-            start = end;
-            end = start + group_size;
-            if remain > 0 {
-                end += 1;
-                remain -= 1;
+    };
+
+    let start = Instant::now();
+    let mut last_checkpoint = start;
+    let output = render_world_with_progress(
+        scene,
+        world,
+        config.threads,
+        config.tile_order,
+        resume_from,
+        |tiles_remaining, output| {
+            eprint!("\rTiles remaining: {tiles_remaining} ");
+            io::stderr().flush().unwrap();
+            if tiles_remaining > 0 && last_checkpoint.elapsed() >= config.checkpoint_interval {
+                save_checkpoint(output);
+                last_checkpoint = Instant::now();
             }
-            results.push(start..end);
-        }
+        },
+    )?;
+    let duration = start.elapsed();
+    save_checkpoint(&output);
+    eprint!("\nDone.\nTime elapsed while rendering: {:?}", duration);
+    if scene.adaptive_sampling.is_some() {
+        eprint!(
+            "\nAverage samples per pixel: {:.1}",
+            output.average_samples_per_pixel()
+        );
+    }
+
+    output::save_image(&output.image, &config.file_name, &config.tone_mapping)?;
+    if let Some(heatmap_file) = &config.heatmap_file {
+        output::sample_heatmap(&output.sample_counts).save(heatmap_file)?;
     }
-    results
-}
 
-fn save_image(image: image::RgbImage, file_name: &Path) -> Result<(), image::ImageError> {
-    image.save(file_name)?;
     Ok(())
 }
 
-pub fn render_and_save(config: RenderConfig) -> Result<(), Box<dyn Error>> {
-    let image = render(config.scene)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes::Scene;
+
+    fn render_pixels(scene: &SceneConfig, threads: usize) -> Vec<Pixel> {
+        let pool = threadpool::ThreadPool::new(threads);
+        let (tx, rx) = channel();
+        render_with_threadpool(
+            &pool,
+            &tx,
+            scene,
+            scene_bvh(scene),
+            TileOrder::Hilbert,
+            false,
+            None,
+            &RenderControl::default(),
+        );
+        drop(tx);
+        let mut pixels: Vec<Pixel> = rx
+            .iter()
+            .flat_map(|message| match message {
+                RenderMessage::TileStarted(_) => Vec::new(),
+                RenderMessage::TileFinished(_, pixels) => pixels,
+            })
+            .collect();
+        pixels.sort_by_key(|&(x, y, _, _)| (y, x));
+        pixels
+    }
 
-    save_image(image, &config.file_name)?;
+    #[test]
+    fn test_render_is_deterministic() {
+        let mut scene = SceneConfig::get_scene(&Scene::CornellBox);
+        scene.image_width = 12;
+        scene.samples_per_pixel = 4;
 
-    Ok(())
+        let first = render_pixels(&scene, 1);
+        assert_eq!(first, render_pixels(&scene, 3));
+
+        scene.seed = 1;
+        assert_ne!(first, render_pixels(&scene, 2));
+    }
+
+    #[test]
+    fn test_progressive_render_adds_up_to_full_render() {
+        assert_eq!(progressive_passes(2), [0..1, 1..2]);
+        assert_eq!(progressive_passes(6), [0..1, 1..2, 2..4, 4..6]);
+
+        let mut scene = SceneConfig::get_scene(&Scene::CornellBox);
+        scene.image_width = 40;
+        scene.samples_per_pixel = 5;
+
+        let mut renderer = ThreadedRenderer {
+            threads_to_use: 2,
+            progressive: true,
+            ..Default::default()
+        };
+        renderer.start_render(scene.clone());
+        while renderer.is_render_in_progress() {
+            renderer.check_progress();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let full = render(&scene).unwrap();
+        for (progressive, full) in renderer.get_float_image().pixels().zip(full.pixels()) {
+            for (a, b) in progressive.0.iter().zip(full.0) {
+                assert!((a - b).abs() <= 1e-4 * b.max(1.0), "{a} != {b}");
+            }
+        }
+        assert!(renderer.sample_counts.iter().all(|&count| count == 5));
+    }
+
+    #[test]
+    fn test_cancel_returns_to_idle() {
+        let mut scene = SceneConfig::get_scene(&Scene::CornellBox);
+        scene.image_width = 64;
+        scene.samples_per_pixel = 2;
+
+        let mut renderer = ThreadedRenderer {
+            threads_to_use: 2,
+            ..Default::default()
+        };
+        renderer.start_render(scene.clone());
+        renderer.pause();
+        assert!(renderer.is_paused());
+        renderer.cancel();
+        assert!(!renderer.is_render_in_progress());
+        assert!(!renderer.is_render_finished());
+        assert!(renderer.is_cancelled());
+        assert!(renderer.tiles_in_flight().is_empty());
+        assert!(!renderer.check_progress());
+
+        renderer.start_render(scene);
+        while renderer.is_render_in_progress() {
+            renderer.check_progress();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(renderer.is_render_finished());
+        assert!(!renderer.is_cancelled());
+    }
+
+    #[test]
+    fn test_adaptive_sampling_stops_early() {
+        let adaptive = AdaptiveSampling::new(8, 0.1);
+
+        let (sum, count) = adaptive.sample_pixel(1000, |_| Color::new(0.5, 0.5, 0.5));
+        assert_eq!(count, 8);
+        assert!((sum.x() - 4.0).abs() < 1e-9);
+
+        let mut bright = false;
+        let (_, count) = adaptive.sample_pixel(1000, |_| {
+            bright = !bright;
+            if bright {
+                Color::new(1.0, 1.0, 1.0)
+            } else {
+                Color::origin()
+            }
+        });
+        assert!(count > 8 && count < 1000, "took {count} samples");
+
+        let (_, count) = AdaptiveSampling::new(8, 1e-6).sample_pixel(100, |_| {
+            bright = !bright;
+            Color::new(bright as u8 as f64, 0.0, 0.0)
+        });
+        assert_eq!(count, 100);
+    }
 }
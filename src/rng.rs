@@ -0,0 +1,30 @@
+use rand::SeedableRng;
+
+/// Random number generator threaded through everything that samples while
+/// rendering: the camera, materials, media and light sampling.
+pub type RenderRng = rand_pcg::Pcg32;
+
+/// Independent random stream for one sample of one pixel.
+///
+/// Deriving a fresh stream per sample makes every sample depend only on the
+/// seed and its position, so a render is identical however its pixels are
+/// split between threads and however many samples a pixel ends up taking.
+pub fn sample_rng(seed: u64, x: u32, y: u32, sample: u32) -> RenderRng {
+    let pixel = ((y as u64) << 32) | x as u64;
+    let key = split_mix64(split_mix64(split_mix64(seed) ^ pixel) ^ sample as u64);
+    RenderRng::seed_from_u64(key)
+}
+
+/// SplitMix64 finalizer, scrambling nearby inputs into unrelated outputs.
+fn split_mix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Generator for calls that need one but are known not to draw from it, such
+/// as intersecting a surface to evaluate a light sampling density.
+pub fn unused_rng() -> RenderRng {
+    RenderRng::new(0, 0)
+}
@@ -0,0 +1,1326 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{
+    de::{value::MapAccessDeserializer, Error, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    aarect::{XYRect, XZRect, YZRect},
+    animated_transform::{AnimatedTransform, Animation, Keyframe},
+    box_struct,
+    bvh::Bvh,
+    camera::{Camera, CameraAnimation, CameraKeyframe},
+    color::Background,
+    constant_medium::ConstantMedium,
+    hittable::{HittablePtr, RotateY, Transform, Translate},
+    hittable_list::HittableList,
+    layered::{Coated, MixMaterial},
+    material::{
+        Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, MaterialPtr, Metal,
+        RoughConductor, RoughDielectric,
+    },
+    matrix::Matrix4,
+    moving_sphere::MovingSphere,
+    principled::Principled,
+    quaternion::Quaternion,
+    render::AdaptiveSampling,
+    scenes::{self, SceneConfig},
+    sphere::Sphere,
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, TexturePtr},
+    triangle_mesh::TriangleMesh,
+    vec3::Vec3,
+};
+
+/// Error produced while loading a scene description file.
+#[derive(Debug)]
+pub enum SceneFileError {
+    /// The file could not be read.
+    Io(PathBuf, io::Error),
+    /// The file is not valid TOML or does not match the scene schema. The
+    /// message contains the line and column of the problem.
+    Parse(PathBuf, toml::de::Error),
+    /// The file parsed, but a value is invalid, e.g. it refers to an unknown
+    /// material. `field` is the path to the offending value.
+    Invalid {
+        path: PathBuf,
+        field: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match self {
+            SceneFileError::Io(path, _)
+            | SceneFileError::Parse(path, _)
+            | SceneFileError::Invalid { path, .. } => path,
+        };
+        if !path.as_os_str().is_empty() {
+            write!(f, "{}: ", path.display())?;
+        }
+
+        match self {
+            SceneFileError::Io(_, e) => write!(f, "{e}"),
+            SceneFileError::Parse(_, e) => write!(f, "{e}"),
+            SceneFileError::Invalid { field, message, .. } => write!(f, "{field}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+/// Load a TOML scene description file into a `SceneConfig`.
+///
+/// Relative texture and mesh paths are resolved against the directory of the
+/// scene file.
+pub fn load_scene_file(path: &Path) -> Result<SceneConfig, SceneFileError> {
+    load_scene_file_spanning(path, &[])
+}
+
+/// Like [`load_scene_file`] for rendering at `times` besides the scene's
+/// own, such as the shutter times of the frames of a sequence, which nested
+/// BVHs then bound their moving objects over.
+pub fn load_scene_file_spanning(path: &Path, times: &[f64]) -> Result<SceneConfig, SceneFileError> {
+    let source = fs::read_to_string(path).map_err(|e| SceneFileError::Io(path.to_path_buf(), e))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene_spanning(&source, base_dir, times).map_err(|e| e.with_path(path))
+}
+
+/// Parse a TOML scene description. Errors are reported without a file name.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<SceneConfig, SceneFileError> {
+    parse_scene_spanning(source, base_dir, &[])
+}
+
+/// Like [`parse_scene`] for rendering at `times` too, as for
+/// [`load_scene_file_spanning`].
+pub fn parse_scene_spanning(
+    source: &str,
+    base_dir: &Path,
+    times: &[f64],
+) -> Result<SceneConfig, SceneFileError> {
+    let file: SceneFile =
+        toml::from_str(source).map_err(|e| SceneFileError::Parse(PathBuf::new(), e))?;
+    SceneBuilder::new(&file, base_dir, times).build()
+}
+
+impl SceneFileError {
+    fn with_path(self, path: &Path) -> SceneFileError {
+        let path = path.to_path_buf();
+        match self {
+            SceneFileError::Io(_, e) => SceneFileError::Io(path, e),
+            SceneFileError::Parse(_, e) => SceneFileError::Parse(path, e),
+            SceneFileError::Invalid { field, message, .. } => SceneFileError::Invalid {
+                path,
+                field,
+                message,
+            },
+        }
+    }
+}
+
+fn invalid(field: &str, message: impl Into<String>) -> SceneFileError {
+    SceneFileError::Invalid {
+        path: PathBuf::new(),
+        field: field.to_string(),
+        message: message.into(),
+    }
+}
+
+fn conductor_ior(desc: &RoughConductorDesc, field: &str) -> Result<(Vec3, Vec3), SceneFileError> {
+    match (&desc.conductor, desc.eta, desc.k) {
+        (Some(name), None, None) => {
+            let conductor = match name.as_str() {
+                "gold" => Conductor::Gold,
+                "copper" => Conductor::Copper,
+                "aluminium" => Conductor::Aluminium,
+                "silver" => Conductor::Silver,
+                _ => {
+                    return Err(invalid(
+                        &format!("{field}.conductor"),
+                        format!(
+                            "unknown conductor \"{name}\" (expected gold, copper, aluminium or \
+                             silver)"
+                        ),
+                    ))
+                }
+            };
+            Ok(conductor.ior())
+        }
+        (None, Some(eta), Some(k)) => Ok((vec3(&eta), vec3(&k))),
+        _ => Err(invalid(
+            field,
+            "expected either conductor or both eta and k",
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    camera: CameraDesc,
+    #[serde(default)]
+    render: RenderDesc,
+    #[serde(default)]
+    background: BackgroundDesc,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    lights: Vec<ObjectDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    #[serde(default = "default_v_up")]
+    v_up: [f64; 3],
+    #[serde(default = "default_vfov")]
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    #[serde(default = "default_focus_dist")]
+    focus_dist: f64,
+    #[serde(default)]
+    time0: f64,
+    #[serde(default = "default_time1")]
+    time1: f64,
+    /// Camera motion for image sequences, with the fields above as defaults.
+    #[serde(default)]
+    keyframes: Vec<CameraKeyframeDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraKeyframeDesc {
+    time: f64,
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    vfov: Option<f64>,
+    aperture: Option<f64>,
+    focus_dist: Option<f64>,
+}
+
+fn default_v_up() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_vfov() -> f64 {
+    20.0
+}
+
+fn default_focus_dist() -> f64 {
+    10.0
+}
+
+fn default_time1() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RenderDesc {
+    image_width: u32,
+    aspect_ratio: f64,
+    samples_per_pixel: u32,
+    /// Enables adaptive sampling, with `samples_per_pixel` as the maximum.
+    adaptive_threshold: Option<f64>,
+    min_samples: u32,
+    max_depth: u32,
+    seed: u64,
+}
+
+impl Default for RenderDesc {
+    fn default() -> Self {
+        Self {
+            image_width: 400,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: 100,
+            adaptive_threshold: None,
+            min_samples: 16,
+            max_depth: 50,
+            seed: 0,
+        }
+    }
+}
+
+/// Declare an enum that is read from a table whose first key, `type`, selects
+/// the variant, e.g. `{ type = "sphere", radius = 1.0, ... }`.
+///
+/// Serde's own internally tagged enums buffer the table before choosing a
+/// variant, which loses the position of errors inside it. Requiring the tag to
+/// come first lets the rest of the table be streamed straight into the
+/// variant's description, so errors point at the offending line and key.
+macro_rules! tagged_enum {
+    ($name:ident { $($tag:literal => $variant:ident($desc:ty),)* }) => {
+        enum $name {
+            $($variant($desc),)*
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct TagVisitor;
+
+                impl<'de> Visitor<'de> for TagVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        write!(f, "a table with a `type` key")
+                    }
+
+                    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<$name, A::Error> {
+                        if map.next_key::<String>()?.as_deref() != Some("type") {
+                            return Err(A::Error::custom("`type` must be the first key"));
+                        }
+                        let tag: String = map.next_value()?;
+                        let rest = MapAccessDeserializer::new(map);
+                        match tag.as_str() {
+                            $($tag => <$desc>::deserialize(rest).map($name::$variant),)*
+                            _ => Err(A::Error::unknown_variant(&tag, &[$($tag),*])),
+                        }
+                    }
+                }
+
+                deserializer.deserialize_map(TagVisitor)
+            }
+        }
+    };
+}
+
+tagged_enum!(BackgroundDesc {
+    "solid" => Solid(SolidBackgroundDesc),
+    "gradient" => Gradient(GradientBackgroundDesc),
+});
+
+impl Default for BackgroundDesc {
+    fn default() -> Self {
+        BackgroundDesc::Solid(SolidBackgroundDesc {
+            color: [0.0, 0.0, 0.0],
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SolidBackgroundDesc {
+    color: [f64; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GradientBackgroundDesc {
+    start: [f64; 3],
+    end: [f64; 3],
+}
+
+/// An inline RGB color, an inline grey value for parameters such as a
+/// roughness, or the name of a texture in `[textures]`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64; 3]),
+    Grey(f64),
+    Name(String),
+}
+
+tagged_enum!(TextureDesc {
+    "solid" => Solid(SolidTextureDesc),
+    "checker" => Checker(CheckerTextureDesc),
+    "noise" => Noise(NoiseTextureDesc),
+    "image" => Image(ImageTextureDesc),
+});
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SolidTextureDesc {
+    color: [f64; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckerTextureDesc {
+    even: TextureRef,
+    odd: TextureRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseTextureDesc {
+    scale: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImageTextureDesc {
+    file: PathBuf,
+}
+
+tagged_enum!(MaterialDesc {
+    "lambertian" => Lambertian(LambertianDesc),
+    "metal" => Metal(MetalDesc),
+    "dielectric" => Dielectric(DielectricDesc),
+    "rough_conductor" => RoughConductor(RoughConductorDesc),
+    "rough_dielectric" => RoughDielectric(RoughDielectricDesc),
+    "principled" => Principled(Box<PrincipledDesc>),
+    "mix" => Mix(MixDesc),
+    "coated" => Coated(CoatedDesc),
+    "diffuse_light" => DiffuseLight(DiffuseLightDesc),
+    "isotropic" => Isotropic(IsotropicDesc),
+});
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LambertianDesc {
+    albedo: TextureRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetalDesc {
+    albedo: [f64; 3],
+    fuzz: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DielectricDesc {
+    ir: f64,
+    #[serde(default)]
+    absorption: [f64; 3],
+}
+
+/// A conductor given either by name or by its complex index of refraction.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoughConductorDesc {
+    conductor: Option<String>,
+    eta: Option<[f64; 3]>,
+    k: Option<[f64; 3]>,
+    roughness: TextureRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoughDielectricDesc {
+    ir: f64,
+    roughness: TextureRef,
+    #[serde(default)]
+    absorption: [f64; 3],
+}
+
+/// Parameters left out keep the defaults of [`Principled::new`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrincipledDesc {
+    base_color: TextureRef,
+    metallic: Option<TextureRef>,
+    roughness: Option<TextureRef>,
+    specular: Option<TextureRef>,
+    specular_tint: Option<TextureRef>,
+    sheen: Option<TextureRef>,
+    sheen_tint: Option<TextureRef>,
+    clearcoat: Option<TextureRef>,
+    clearcoat_roughness: Option<TextureRef>,
+    transmission: Option<TextureRef>,
+    ir: Option<f64>,
+}
+
+/// Blend of two materials of the scene, by name.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MixDesc {
+    first: String,
+    second: String,
+    weight: TextureRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CoatedDesc {
+    base: String,
+    ir: f64,
+    roughness: TextureRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiffuseLightDesc {
+    emit: TextureRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IsotropicDesc {
+    albedo: TextureRef,
+}
+
+tagged_enum!(ObjectDesc {
+    "sphere" => Sphere(SphereDesc),
+    "moving_sphere" => MovingSphere(MovingSphereDesc),
+    "xy_rect" => XYRect(XYRectDesc),
+    "xz_rect" => XZRect(XZRectDesc),
+    "yz_rect" => YZRect(YZRectDesc),
+    "box" => Box(BoxDesc),
+    "mesh" => Mesh(MeshDesc),
+    "constant_medium" => ConstantMedium(ConstantMediumDesc),
+    "translate" => Translate(TranslateDesc),
+    "rotate_y" => RotateY(RotateYDesc),
+    "transform" => Transform(TransformDesc),
+    "animated_transform" => AnimatedTransform(AnimatedTransformDesc),
+    "bvh" => Bvh(BvhDesc),
+});
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereDesc {
+    center: [f64; 3],
+    radius: f64,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MovingSphereDesc {
+    center0: [f64; 3],
+    center1: [f64; 3],
+    #[serde(default)]
+    time0: f64,
+    #[serde(default = "default_time1")]
+    time1: f64,
+    radius: f64,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct XYRectDesc {
+    x0: f64,
+    x1: f64,
+    y0: f64,
+    y1: f64,
+    k: f64,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct XZRectDesc {
+    x0: f64,
+    x1: f64,
+    z0: f64,
+    z1: f64,
+    k: f64,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct YZRectDesc {
+    y0: f64,
+    y1: f64,
+    z0: f64,
+    z1: f64,
+    k: f64,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoxDesc {
+    min: [f64; 3],
+    max: [f64; 3],
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshDesc {
+    file: PathBuf,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConstantMediumDesc {
+    boundary: Box<ObjectDesc>,
+    density: f64,
+    albedo: TextureRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TranslateDesc {
+    offset: [f64; 3],
+    object: Box<ObjectDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotateYDesc {
+    angle: f64,
+    object: Box<ObjectDesc>,
+}
+
+/// Scales `object`, then applies `rotate` in order, then translates it.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformDesc {
+    #[serde(default = "default_scale")]
+    scale: [f64; 3],
+    #[serde(default)]
+    rotate: Vec<RotationDesc>,
+    #[serde(default)]
+    translate: [f64; 3],
+    object: Box<ObjectDesc>,
+}
+
+fn default_scale() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationDesc {
+    axis: [f64; 3],
+    angle: f64,
+}
+
+/// Moves `object` through `keyframes`, interpolating between them.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimatedTransformDesc {
+    keyframes: Vec<KeyframeDesc>,
+    object: Box<ObjectDesc>,
+}
+
+/// Pose at `time`, built like a [`TransformDesc`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    #[serde(default = "default_scale")]
+    scale: [f64; 3],
+    #[serde(default)]
+    rotate: Vec<RotationDesc>,
+    #[serde(default)]
+    translate: [f64; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BvhDesc {
+    objects: Vec<ObjectDesc>,
+}
+
+fn vec3(e: &[f64; 3]) -> Vec3 {
+    Vec3::new(e[0], e[1], e[2])
+}
+
+/// The `absorption` of the glass material at `field`, which would amplify
+/// light if negative.
+fn absorption_coefficients(absorption: &[f64; 3], field: &str) -> Result<Vec3, SceneFileError> {
+    if absorption.iter().any(|a| !(0.0..).contains(a)) {
+        return Err(invalid(
+            &format!("{field}.absorption"),
+            "must not be negative",
+        ));
+    }
+    Ok(vec3(absorption))
+}
+
+/// Collect the times at which `desc` or the objects inside it are keyed.
+fn object_times(desc: &ObjectDesc, times: &mut Vec<f64>) {
+    match desc {
+        ObjectDesc::MovingSphere(MovingSphereDesc { time0, time1, .. }) => {
+            times.extend([*time0, *time1]);
+        }
+        ObjectDesc::AnimatedTransform(AnimatedTransformDesc { keyframes, object }) => {
+            times.extend(keyframes.iter().map(|k| k.time));
+            object_times(object, times);
+        }
+        ObjectDesc::ConstantMedium(ConstantMediumDesc {
+            boundary: object, ..
+        })
+        | ObjectDesc::Translate(TranslateDesc { object, .. })
+        | ObjectDesc::RotateY(RotateYDesc { object, .. })
+        | ObjectDesc::Transform(TransformDesc { object, .. }) => object_times(object, times),
+        ObjectDesc::Bvh(BvhDesc { objects }) => {
+            for object in objects {
+                object_times(object, times);
+            }
+        }
+        ObjectDesc::Sphere(_)
+        | ObjectDesc::XYRect(_)
+        | ObjectDesc::XZRect(_)
+        | ObjectDesc::YZRect(_)
+        | ObjectDesc::Box(_)
+        | ObjectDesc::Mesh(_) => {}
+    }
+}
+
+/// Validate the scale of a transform and combine its rotations, applied in
+/// order, into one.
+fn scale_and_rotation(
+    scale: &[f64; 3],
+    rotate: &[RotationDesc],
+    field: &str,
+) -> Result<(Vec3, Quaternion), SceneFileError> {
+    if scale.contains(&0.0) {
+        return Err(invalid(&format!("{field}.scale"), "must not be zero"));
+    }
+    let mut rotation = Quaternion::identity();
+    for (i, RotationDesc { axis, angle }) in rotate.iter().enumerate() {
+        if vec3(axis).near_zero() {
+            return Err(invalid(
+                &format!("{field}.rotate[{i}].axis"),
+                "must not be zero",
+            ));
+        }
+        rotation = Quaternion::from_axis_angle(&vec3(axis), *angle) * rotation;
+    }
+    Ok((vec3(scale), rotation))
+}
+
+struct SceneBuilder<'a> {
+    file: &'a SceneFile,
+    base_dir: &'a Path,
+    textures: HashMap<String, TexturePtr>,
+    materials: HashMap<String, MaterialPtr>,
+    /// Times from the first to the last keyframe or shutter time, including
+    /// those the scene is to be rendered at, over which nested BVHs have to
+    /// bound their objects.
+    time_span: (f64, f64),
+}
+
+impl<'a> SceneBuilder<'a> {
+    fn new(file: &'a SceneFile, base_dir: &'a Path, render_times: &[f64]) -> SceneBuilder<'a> {
+        let mut times = vec![file.camera.time0, file.camera.time1];
+        times.extend_from_slice(render_times);
+        times.extend(file.camera.keyframes.iter().map(|k| k.time));
+        for desc in file.objects.iter().chain(&file.lights) {
+            object_times(desc, &mut times);
+        }
+        let time_span = times
+            .iter()
+            .fold((f64::INFINITY, -f64::INFINITY), |(min, max), &t| {
+                (min.min(t), max.max(t))
+            });
+
+        SceneBuilder {
+            file,
+            base_dir,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            time_span,
+        }
+    }
+
+    fn build(mut self) -> Result<SceneConfig, SceneFileError> {
+        let file = self.file;
+        let render = &file.render;
+        if render.image_width < 2 {
+            return Err(invalid("render.image_width", "must be at least 2"));
+        }
+        if render.aspect_ratio <= 0.0 {
+            return Err(invalid("render.aspect_ratio", "must be positive"));
+        }
+        if scenes::image_height(render.image_width, render.aspect_ratio) < 2 {
+            return Err(invalid(
+                "render.aspect_ratio",
+                "must leave the image at least 2 pixels high",
+            ));
+        }
+        if render.samples_per_pixel == 0 {
+            return Err(invalid("render.samples_per_pixel", "must be at least 1"));
+        }
+        if render
+            .adaptive_threshold
+            .is_some_and(|threshold| threshold <= 0.0)
+        {
+            return Err(invalid("render.adaptive_threshold", "must be positive"));
+        }
+        if file.camera.time1 < file.camera.time0 {
+            return Err(invalid("camera.time1", "must not be before camera.time0"));
+        }
+
+        for name in file.textures.keys() {
+            self.texture_by_name(name, &format!("textures.{name}"), 0)?;
+        }
+        for name in file.materials.keys() {
+            self.material_by_name(name, &format!("materials.{name}"), 0)?;
+        }
+
+        let mut world = HittableList::new();
+        for (i, desc) in file.objects.iter().enumerate() {
+            world.add(self.object(desc, &format!("objects[{i}]"))?);
+        }
+
+        let mut lights = HittableList::new();
+        for (i, desc) in file.lights.iter().enumerate() {
+            let field = format!("lights[{i}]");
+            let light = self.object(desc, &field)?;
+            if !light.can_sample() {
+                return Err(invalid(
+                    &field,
+                    "can't be sampled as a light, which must be made of spheres and rects",
+                ));
+            }
+            lights.add(light);
+        }
+
+        let camera_desc = &file.camera;
+        let camera = Camera::new(
+            vec3(&camera_desc.look_from),
+            vec3(&camera_desc.look_at),
+            vec3(&camera_desc.v_up),
+            camera_desc.vfov,
+            render.aspect_ratio,
+            camera_desc.aperture,
+            camera_desc.focus_dist,
+            camera_desc.time0,
+            camera_desc.time1,
+        );
+
+        let camera_animation = if camera_desc.keyframes.is_empty() {
+            None
+        } else {
+            let keyframes = camera_desc
+                .keyframes
+                .iter()
+                .map(|k| CameraKeyframe {
+                    time: k.time,
+                    look_from: vec3(&k.look_from.unwrap_or(camera_desc.look_from)),
+                    look_at: vec3(&k.look_at.unwrap_or(camera_desc.look_at)),
+                    vfov_degrees: k.vfov.unwrap_or(camera_desc.vfov),
+                    aperture: k.aperture.unwrap_or(camera_desc.aperture),
+                    focus_dist: k.focus_dist.unwrap_or(camera_desc.focus_dist),
+                })
+                .collect();
+            Some(CameraAnimation::new(keyframes))
+        };
+
+        let background = match &file.background {
+            BackgroundDesc::Solid(SolidBackgroundDesc { color }) => Background::Solid(vec3(color)),
+            BackgroundDesc::Gradient(GradientBackgroundDesc { start, end }) => {
+                Background::Gradient(vec3(start), vec3(end))
+            }
+        };
+
+        Ok(SceneConfig {
+            camera,
+            camera_animation,
+            world,
+            lights,
+            background,
+            image_width: render.image_width,
+            aspect_ratio: render.aspect_ratio,
+            samples_per_pixel: render.samples_per_pixel,
+            adaptive_sampling: render
+                .adaptive_threshold
+                .map(|threshold| AdaptiveSampling::new(render.min_samples, threshold)),
+            max_depth: render.max_depth,
+            seed: render.seed,
+            time_limit: None,
+        })
+    }
+
+    fn texture_by_name(
+        &mut self,
+        name: &str,
+        field: &str,
+        depth: usize,
+    ) -> Result<TexturePtr, SceneFileError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(Arc::clone(texture));
+        }
+        let desc = self
+            .file
+            .textures
+            .get(name)
+            .ok_or_else(|| invalid(field, format!("unknown texture \"{name}\"")))?;
+        // Textures can refer to each other, so guard against cycles.
+        if depth > self.file.textures.len() {
+            return Err(invalid(
+                field,
+                format!("texture \"{name}\" refers to itself"),
+            ));
+        }
+
+        let field = format!("textures.{name}");
+        let texture: TexturePtr = match desc {
+            TextureDesc::Solid(SolidTextureDesc { color }) => {
+                Arc::new(SolidColor::new(vec3(color)))
+            }
+            TextureDesc::Checker(CheckerTextureDesc { even, odd }) => {
+                let even = self.texture_ref(even, &format!("{field}.even"), depth + 1)?;
+                let odd = self.texture_ref(odd, &format!("{field}.odd"), depth + 1)?;
+                Arc::new(CheckerTexture::new(&even, &odd))
+            }
+            TextureDesc::Noise(NoiseTextureDesc { scale }) => Arc::new(NoiseTexture::new(*scale)),
+            TextureDesc::Image(ImageTextureDesc { file }) => {
+                let file = self.resolve_path(file);
+                Arc::new(ImageTexture::new(&file.to_string_lossy()))
+            }
+        };
+
+        self.textures.insert(name.to_string(), Arc::clone(&texture));
+        Ok(texture)
+    }
+
+    fn texture_ref(
+        &mut self,
+        texture: &TextureRef,
+        field: &str,
+        depth: usize,
+    ) -> Result<TexturePtr, SceneFileError> {
+        match texture {
+            TextureRef::Color(color) => Ok(Arc::new(SolidColor::new(vec3(color)))),
+            TextureRef::Grey(value) => Ok(Arc::new(SolidColor::new_grey(*value))),
+            TextureRef::Name(name) => self.texture_by_name(name, field, depth),
+        }
+    }
+
+    fn material(
+        &mut self,
+        desc: &MaterialDesc,
+        field: &str,
+        depth: usize,
+    ) -> Result<MaterialPtr, SceneFileError> {
+        let material: MaterialPtr = match desc {
+            MaterialDesc::Lambertian(LambertianDesc { albedo }) => {
+                let albedo = self.texture_ref(albedo, &format!("{field}.albedo"), 0)?;
+                Arc::new(Lambertian::new(&albedo))
+            }
+            MaterialDesc::Metal(MetalDesc { albedo, fuzz }) => {
+                Arc::new(Metal::new(&vec3(albedo), *fuzz))
+            }
+            MaterialDesc::Dielectric(DielectricDesc { ir, absorption }) => {
+                let absorption = absorption_coefficients(absorption, field)?;
+                Arc::new(Dielectric::new(*ir).with_absorption(absorption))
+            }
+            MaterialDesc::RoughConductor(desc) => {
+                let (eta, k) = conductor_ior(desc, field)?;
+                let roughness =
+                    self.texture_ref(&desc.roughness, &format!("{field}.roughness"), 0)?;
+                Arc::new(RoughConductor::new(&eta, &k, &roughness))
+            }
+            MaterialDesc::RoughDielectric(RoughDielectricDesc {
+                ir,
+                roughness,
+                absorption,
+            }) => {
+                let roughness = self.texture_ref(roughness, &format!("{field}.roughness"), 0)?;
+                let absorption = absorption_coefficients(absorption, field)?;
+                Arc::new(RoughDielectric::new(*ir, &roughness).with_absorption(absorption))
+            }
+            MaterialDesc::Principled(desc) => Arc::new(self.principled(desc, field)?),
+            MaterialDesc::Mix(MixDesc {
+                first,
+                second,
+                weight,
+            }) => {
+                let first = self.material_by_name(first, &format!("{field}.first"), depth + 1)?;
+                let second =
+                    self.material_by_name(second, &format!("{field}.second"), depth + 1)?;
+                let weight = self.texture_ref(weight, &format!("{field}.weight"), 0)?;
+                Arc::new(MixMaterial::new(&first, &second, &weight))
+            }
+            MaterialDesc::Coated(CoatedDesc {
+                base,
+                ir,
+                roughness,
+            }) => {
+                let base = self.material_by_name(base, &format!("{field}.base"), depth + 1)?;
+                let roughness = self.texture_ref(roughness, &format!("{field}.roughness"), 0)?;
+                Arc::new(Coated::new(&base, *ir, &roughness))
+            }
+            MaterialDesc::DiffuseLight(DiffuseLightDesc { emit }) => {
+                let emit = self.texture_ref(emit, &format!("{field}.emit"), 0)?;
+                Arc::new(DiffuseLight::new(&emit))
+            }
+            MaterialDesc::Isotropic(IsotropicDesc { albedo }) => {
+                let albedo = self.texture_ref(albedo, &format!("{field}.albedo"), 0)?;
+                Arc::new(Isotropic::new(&albedo))
+            }
+        };
+        Ok(material)
+    }
+
+    fn principled(
+        &mut self,
+        desc: &PrincipledDesc,
+        field: &str,
+    ) -> Result<Principled, SceneFileError> {
+        let base_color = self.texture_ref(&desc.base_color, &format!("{field}.base_color"), 0)?;
+        let mut principled = Principled::new(&base_color);
+        let parameters = [
+            (&desc.metallic, "metallic", &mut principled.metallic),
+            (&desc.roughness, "roughness", &mut principled.roughness),
+            (&desc.specular, "specular", &mut principled.specular),
+            (
+                &desc.specular_tint,
+                "specular_tint",
+                &mut principled.specular_tint,
+            ),
+            (&desc.sheen, "sheen", &mut principled.sheen),
+            (&desc.sheen_tint, "sheen_tint", &mut principled.sheen_tint),
+            (&desc.clearcoat, "clearcoat", &mut principled.clearcoat),
+            (
+                &desc.clearcoat_roughness,
+                "clearcoat_roughness",
+                &mut principled.clearcoat_roughness,
+            ),
+            (
+                &desc.transmission,
+                "transmission",
+                &mut principled.transmission,
+            ),
+        ];
+        for (texture, name, parameter) in parameters {
+            if let Some(texture) = texture {
+                *parameter = self.texture_ref(texture, &format!("{field}.{name}"), 0)?;
+            }
+        }
+        if let Some(ir) = desc.ir {
+            principled.ir = ir;
+        }
+        Ok(principled)
+    }
+
+    fn material_by_name(
+        &mut self,
+        name: &str,
+        field: &str,
+        depth: usize,
+    ) -> Result<MaterialPtr, SceneFileError> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(Arc::clone(material));
+        }
+        let desc = self
+            .file
+            .materials
+            .get(name)
+            .ok_or_else(|| invalid(field, format!("unknown material \"{name}\"")))?;
+        // Materials can be made of each other, so guard against cycles.
+        if depth > self.file.materials.len() {
+            return Err(invalid(
+                field,
+                format!("material \"{name}\" is made of itself"),
+            ));
+        }
+
+        let material = self.material(desc, &format!("materials.{name}"), depth)?;
+        self.materials
+            .insert(name.to_string(), Arc::clone(&material));
+        Ok(material)
+    }
+
+    fn object(&mut self, desc: &ObjectDesc, field: &str) -> Result<HittablePtr, SceneFileError> {
+        let material_field = format!("{field}.material");
+        let object: HittablePtr = match desc {
+            ObjectDesc::Sphere(SphereDesc {
+                center,
+                radius,
+                material,
+            }) => {
+                let material = self.material_by_name(material, &material_field, 0)?;
+                Arc::new(Sphere::new(vec3(center), *radius, &material))
+            }
+            ObjectDesc::MovingSphere(MovingSphereDesc {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            }) => {
+                if time1 <= time0 {
+                    return Err(invalid(&format!("{field}.time1"), "must be after time0"));
+                }
+                let material = self.material_by_name(material, &material_field, 0)?;
+                Arc::new(MovingSphere::new(
+                    vec3(center0),
+                    vec3(center1),
+                    *time0,
+                    *time1,
+                    *radius,
+                    &material,
+                ))
+            }
+            ObjectDesc::XYRect(XYRectDesc {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            }) => {
+                let material = self.material_by_name(material, &material_field, 0)?;
+                Arc::new(XYRect::new(*x0, *x1, *y0, *y1, *k, &material))
+            }
+            ObjectDesc::XZRect(XZRectDesc {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            }) => {
+                let material = self.material_by_name(material, &material_field, 0)?;
+                Arc::new(XZRect::new(*x0, *x1, *z0, *z1, *k, &material))
+            }
+            ObjectDesc::YZRect(YZRectDesc {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            }) => {
+                let material = self.material_by_name(material, &material_field, 0)?;
+                Arc::new(YZRect::new(*y0, *y1, *z0, *z1, *k, &material))
+            }
+            ObjectDesc::Box(BoxDesc { min, max, material }) => {
+                let material = self.material_by_name(material, &material_field, 0)?;
+                Arc::new(box_struct::Box::new(&vec3(min), &vec3(max), &material))
+            }
+            ObjectDesc::Mesh(MeshDesc { file, material }) => {
+                let material = self.material_by_name(material, &material_field, 0)?;
+                let file = self.resolve_path(file);
+                let mesh = TriangleMesh::load_obj(&file, &material)
+                    .map_err(|e| invalid(&format!("{field}.file"), e.to_string()))?;
+                Arc::new(mesh)
+            }
+            ObjectDesc::ConstantMedium(ConstantMediumDesc {
+                boundary,
+                density,
+                albedo,
+            }) => {
+                if *density <= 0.0 {
+                    return Err(invalid(&format!("{field}.density"), "must be positive"));
+                }
+                let boundary = self.object(boundary, &format!("{field}.boundary"))?;
+                let albedo = self.texture_ref(albedo, &format!("{field}.albedo"), 0)?;
+                Arc::new(ConstantMedium::new(&boundary, *density, &albedo))
+            }
+            ObjectDesc::Translate(TranslateDesc { offset, object }) => {
+                let object = self.object(object, &format!("{field}.object"))?;
+                Arc::new(Translate::new(&object, &vec3(offset)))
+            }
+            ObjectDesc::RotateY(RotateYDesc { angle, object }) => {
+                let object = self.object(object, &format!("{field}.object"))?;
+                Arc::new(RotateY::new(&object, *angle))
+            }
+            ObjectDesc::Transform(TransformDesc {
+                scale,
+                rotate,
+                translate,
+                object,
+            }) => {
+                let (scale, rotation) = scale_and_rotation(scale, rotate, field)?;
+                let matrix = Matrix4::translation(&vec3(translate))
+                    * rotation.to_matrix()
+                    * Matrix4::scaling(&scale);
+                let object = self.object(object, &format!("{field}.object"))?;
+                Arc::new(Transform::new(&object, &matrix))
+            }
+            ObjectDesc::AnimatedTransform(AnimatedTransformDesc { keyframes, object }) => {
+                if keyframes.is_empty() {
+                    return Err(invalid(&format!("{field}.keyframes"), "must not be empty"));
+                }
+                let mut poses = Vec::new();
+                for (i, keyframe) in keyframes.iter().enumerate() {
+                    let keyframe_field = format!("{field}.keyframes[{i}]");
+                    let (scale, rotation) =
+                        scale_and_rotation(&keyframe.scale, &keyframe.rotate, &keyframe_field)?;
+                    poses.push(Keyframe::new(
+                        keyframe.time,
+                        scale,
+                        rotation,
+                        vec3(&keyframe.translate),
+                    ));
+                }
+                let object = self.object(object, &format!("{field}.object"))?;
+                Arc::new(AnimatedTransform::new(&object, Animation::new(poses)))
+            }
+            ObjectDesc::Bvh(BvhDesc { objects }) => {
+                if objects.is_empty() {
+                    return Err(invalid(&format!("{field}.objects"), "must not be empty"));
+                }
+                let mut list = HittableList::new();
+                for (i, desc) in objects.iter().enumerate() {
+                    list.add(self.object(desc, &format!("{field}.objects[{i}]"))?);
+                }
+                Arc::new(Bvh::from_hittable_list(
+                    &list,
+                    self.time_span.0,
+                    self.time_span.1,
+                ))
+            }
+        };
+        Ok(object)
+    }
+
+    fn resolve_path(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.base_dir.join(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable::HitRecord, ray::Ray, rng, vec3::Point3};
+
+    const SCENE: &str = r#"
+[camera]
+look_from = [0.0, 0.0, 5.0]
+look_at = [0.0, 0.0, 0.0]
+
+[render]
+image_width = 200
+aspect_ratio = 2.0
+
+[textures.checker]
+type = "checker"
+even = [0.2, 0.3, 0.1]
+odd = "white"
+
+[textures.white]
+type = "solid"
+color = [0.9, 0.9, 0.9]
+
+[materials.ground]
+type = "lambertian"
+albedo = "checker"
+
+[[objects]]
+type = "translate"
+offset = [0.0, 1.0, 0.0]
+
+[objects.object]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "ground"
+"#;
+
+    #[test]
+    fn test_parse_scene() {
+        let scene = parse_scene(SCENE, Path::new("")).expect("Scene should parse");
+        assert_eq!(scene.image_size(), (200, 100));
+        assert_eq!(scene.samples_per_pixel, 100);
+        assert_eq!(scene.world.objects.len(), 1);
+    }
+
+    #[test]
+    fn test_nested_bvh_time_span() {
+        // A sphere in a BVH group, still moving after its keyed times.
+        let moving = SCENE.replace(
+            "type = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0",
+            "type = \"bvh\"\n\n[[objects.object.objects]]\ntype = \"moving_sphere\"\n\
+             center0 = [0.0, 0.0, 0.0]\ncenter1 = [10.0, 0.0, 0.0]\nradius = 1.0",
+        );
+        let ray = Ray::new(Point3::new(30.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 3.0);
+        let hits = |scene: &SceneConfig| {
+            let mut rec = HitRecord::empty();
+            scene.world.objects[0].hit(&ray, 0.001, f64::INFINITY, &mut rec, &mut rng::unused_rng())
+        };
+
+        let scene =
+            parse_scene_spanning(&moving, Path::new(""), &[0.0, 3.0]).expect("Scene should parse");
+        assert!(hits(&scene));
+    }
+
+    #[test]
+    fn test_parse_scene_errors() {
+        let unknown_material = SCENE.replace("material = \"ground\"", "material = \"metal\"");
+        let error = parse_scene(&unknown_material, Path::new("")).err().unwrap();
+        assert!(
+            error.to_string().contains("objects[0].object.material"),
+            "{error}"
+        );
+
+        let bad_type = SCENE.replace("radius = 1.0", "radius = \"big\"");
+        let error = parse_scene(&bad_type, Path::new("")).err().unwrap();
+        assert!(error.to_string().contains("line 30, column 10"), "{error}");
+
+        // 2 pixels wide at the default aspect ratio is 1 pixel high.
+        let narrow = SCENE.replace("image_width = 200\naspect_ratio = 2.0", "image_width = 2");
+        let error = parse_scene(&narrow, Path::new("")).err().unwrap();
+        assert!(error.to_string().contains("render.aspect_ratio"), "{error}");
+        let wide = SCENE.replace("aspect_ratio = 2.0", "aspect_ratio = 500.0");
+        let error = parse_scene(&wide, Path::new("")).err().unwrap();
+        assert!(error.to_string().contains("render.aspect_ratio"), "{error}");
+
+        let box_light = format!(
+            "{SCENE}\n[[lights]]\ntype = \"box\"\nmin = [0.0, 0.0, 0.0]\n\
+             max = [1.0, 1.0, 1.0]\nmaterial = \"ground\"\n"
+        );
+        let error = parse_scene(&box_light, Path::new("")).err().unwrap();
+        assert!(
+            error.to_string().contains("lights[0]: can't be sampled"),
+            "{error}"
+        );
+
+        let flat = SCENE.replace(
+            "type = \"translate\"\noffset = [0.0, 1.0, 0.0]",
+            "type = \"transform\"\nscale = [1.0, 0.0, 1.0]",
+        );
+        let error = parse_scene(&flat, Path::new("")).err().unwrap();
+        assert!(error.to_string().contains("objects[0].scale"), "{error}");
+
+        let gold = SCENE.replace(
+            "type = \"lambertian\"\nalbedo = \"checker\"",
+            "type = \"rough_conductor\"\nconductor = \"gold\"\nroughness = 0.3",
+        );
+        assert!(parse_scene(&gold, Path::new("")).is_ok());
+        let plastic = SCENE.replace(
+            "type = \"lambertian\"\nalbedo = \"checker\"",
+            "type = \"principled\"\nbase_color = \"checker\"\nclearcoat = 1.0",
+        );
+        assert!(parse_scene(&plastic, Path::new("")).is_ok());
+        let amplifying = SCENE.replace(
+            "type = \"lambertian\"\nalbedo = \"checker\"",
+            "type = \"dielectric\"\nir = 1.5\nabsorption = [0.5, -0.1, 2.0]",
+        );
+        let error = parse_scene(&amplifying, Path::new("")).err().unwrap();
+        assert!(
+            error.to_string().contains("materials.ground.absorption"),
+            "{error}"
+        );
+
+        // Materials can be made of ones defined after them, but not of
+        // themselves.
+        let varnished = SCENE.replace(
+            "type = \"lambertian\"\nalbedo = \"checker\"",
+            "type = \"coated\"\nbase = \"rusty\"\nir = 1.5\nroughness = 0.1\n\n\
+             [materials.rusty]\ntype = \"mix\"\nfirst = \"wood\"\nsecond = \"wood\"\n\
+             weight = \"checker\"\n\n[materials.wood]\ntype = \"lambertian\"\n\
+             albedo = [0.5, 0.3, 0.1]",
+        );
+        assert!(parse_scene(&varnished, Path::new("")).is_ok());
+        let cycle = varnished.replace("second = \"wood\"", "second = \"ground\"");
+        let error = parse_scene(&cycle, Path::new("")).err().unwrap();
+        assert!(error.to_string().contains("is made of itself"), "{error}");
+        let brass = gold.replace("\"gold\"", "\"brass\"");
+        let error = parse_scene(&brass, Path::new("")).err().unwrap();
+        assert!(
+            error.to_string().contains("materials.ground.conductor"),
+            "{error}"
+        );
+    }
+}
@@ -1,20 +1,22 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use rand::{Rng, SeedableRng};
 
 use crate::{
     aarect::{XYRect, XZRect, YZRect},
     box_struct::Box,
-    bvh::BVHNode,
-    camera::Camera,
+    bvh::Bvh,
+    camera::{Camera, CameraAnimation},
     color::Background,
     constant_medium::ConstantMedium,
     hittable::{HittablePtr, RotateY, Translate},
     hittable_list::HittableList,
     material::{Dielectric, DiffuseLight, Lambertian, Material, MaterialPtr, Metal},
     moving_sphere::MovingSphere,
+    render::AdaptiveSampling,
     sphere::Sphere,
     texture::{CheckerTexture, ImageTexture, NoiseTexture, TexturePtr},
+    triangle_mesh::TriangleMesh,
     vec3::{Color, Point3, Vec3},
 };
 
@@ -28,16 +30,32 @@ pub enum Scene {
     CornellBox,
     CornellSmoke,
     FinalScene,
+    Mesh,
 }
 
+#[derive(Clone)]
 pub struct SceneConfig {
     pub camera: Camera,
+    /// Motion of `camera` when rendering an image sequence.
+    pub camera_animation: Option<CameraAnimation>,
     pub world: HittableList,
+    /// Objects to sample directly as light sources. Usually the emitters of
+    /// `world`, without the objects they might be wrapped in.
+    pub lights: HittableList,
     pub background: Background,
     pub image_width: u32,
     pub aspect_ratio: f64,
+    /// Samples per pixel, or the most samples a pixel may take when
+    /// sampling adaptively.
     pub samples_per_pixel: u32,
+    pub adaptive_sampling: Option<AdaptiveSampling>,
     pub max_depth: u32,
+    /// Seed of the random streams the samples are drawn from. Renders with
+    /// the same seed are identical.
+    pub seed: u64,
+    /// Once a render has taken this long, the pixels still to go take a
+    /// single sample each.
+    pub time_limit: Option<Duration>,
 }
 
 impl SceneConfig {
@@ -50,6 +68,7 @@ impl SceneConfig {
         let mut look_from = Point3::new(13.0, 2.0, 3.0);
         let mut look_at = Point3::new(0.0, 0.0, 0.0);
         let world;
+        let mut lights = HittableList::new();
         let focus_dist = 10.0;
         let mut background = Background::Solid(Color::origin());
 
@@ -77,13 +96,13 @@ impl SceneConfig {
                 background = Background::Solid(Color::new(0.70, 0.80, 1.00));
             }
             Scene::SimpleLight => {
-                world = simple_light();
+                (world, lights) = simple_light();
                 look_from = Point3::new(26.0, 3.0, 6.0);
                 look_at = Point3::new(0.0, 2.0, 0.0);
                 sample_per_pixel = 400;
             }
             Scene::CornellBox => {
-                world = cornell_box();
+                (world, lights) = cornell_box();
                 look_from = Point3::new(278.0, 278.0, -800.0);
                 look_at = Point3::new(278.0, 278.0, 0.0);
                 v_fov = 40.0;
@@ -92,7 +111,7 @@ impl SceneConfig {
                 sample_per_pixel = 200;
             }
             Scene::CornellSmoke => {
-                world = cornell_smoke();
+                (world, lights) = cornell_smoke();
                 look_from = Point3::new(278.0, 278.0, -800.0);
                 look_at = Point3::new(278.0, 278.0, 0.0);
                 v_fov = 40.0;
@@ -101,7 +120,7 @@ impl SceneConfig {
                 sample_per_pixel = 200;
             }
             Scene::FinalScene => {
-                world = final_scene();
+                (world, lights) = final_scene();
                 look_from = Point3::new(478.0, 278.0, -600.0);
                 look_at = Point3::new(278.0, 278.0, 0.0);
                 v_fov = 40.0;
@@ -109,6 +128,10 @@ impl SceneConfig {
                 image_width = 800;
                 sample_per_pixel = 10000;
             }
+            Scene::Mesh => {
+                world = mesh();
+                background = Background::Solid(Color::new(0.70, 0.80, 1.00));
+            }
         }
         let camera = Camera::new(
             look_from,
@@ -123,21 +146,50 @@ impl SceneConfig {
         );
         SceneConfig {
             camera,
+            camera_animation: None,
             world,
+            lights,
             background,
             image_width,
             aspect_ratio,
             samples_per_pixel: sample_per_pixel,
+            adaptive_sampling: None,
             max_depth,
+            seed: 0,
+            time_limit: None,
         }
     }
 
+    /// Open the camera shutter from `open` to `close`, moving the camera
+    /// along its animation to where it is half way through.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        let camera = self.camera.with_shutter(open, close);
+        self.camera = match &self.camera_animation {
+            Some(animation) => camera.with_keyframe(&animation.at(0.5 * (open + close))),
+            None => camera,
+        };
+    }
+
+    /// Change the aspect ratio of the image and the camera's viewport.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.aspect_ratio = aspect_ratio;
+        self.camera = self.camera.with_aspect_ratio(aspect_ratio);
+    }
+
     pub fn image_size(&self) -> (u32, u32) {
-        let image_height = (self.image_width as f64 / self.aspect_ratio) as u32;
-        (self.image_width, image_height)
+        (
+            self.image_width,
+            image_height(self.image_width, self.aspect_ratio),
+        )
     }
 }
 
+/// Height of an image `image_width` pixels wide with `aspect_ratio`, rounded
+/// so that the aspect ratio of a width and height gives that height back.
+pub fn image_height(image_width: u32, aspect_ratio: f64) -> u32 {
+    (image_width as f64 / aspect_ratio).round() as u32
+}
+
 fn random_scene() -> HittableList {
     const RANDOM_SEED: u64 = 2;
 
@@ -169,7 +221,7 @@ fn random_scene() -> HittableList {
                 let sphere_material: Arc<dyn Material>;
                 if choose_mat < 0.8 {
                     // diffuse
-                    let albedo = Color::random() * Color::random();
+                    let albedo = Color::random(&mut rng) * Color::random(&mut rng);
                     sphere_material = Arc::new(Lambertian::new_from_color(&albedo));
                     let center2 = center + Vec3::new(0.0, rng.gen_range(0.0..0.5), 0.0);
                     world.add(Arc::new(MovingSphere::new(
@@ -182,7 +234,7 @@ fn random_scene() -> HittableList {
                     )));
                 } else if choose_mat < 0.95 {
                     // metal
-                    let albedo = Color::random_range(0.5, 1.0);
+                    let albedo = Color::random_range(0.5, 1.0, &mut rng);
                     let fuzz = rng.gen_range(0.0..0.5);
                     sphere_material = Arc::new(Metal::new(&albedo, fuzz));
                     world.add(Arc::new(Sphere::new(center, 0.2, &sphere_material)));
@@ -307,8 +359,9 @@ fn earth() -> HittableList {
     world
 }
 
-fn simple_light() -> HittableList {
+fn simple_light() -> (HittableList, HittableList) {
     let mut world = HittableList::new();
+    let mut lights = HittableList::new();
 
     let per_text: TexturePtr = Arc::new(NoiseTexture::new(4.0));
 
@@ -327,19 +380,21 @@ fn simple_light() -> HittableList {
 
     let diff_light: MaterialPtr =
         Arc::new(DiffuseLight::new_from_color(&Color::new(4.0, 4.0, 4.0)));
-    world.add(Arc::new(XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, &diff_light)));
+    let rect_light: HittablePtr = Arc::new(XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, &diff_light));
+    world.add(Arc::clone(&rect_light));
+    lights.add(rect_light);
 
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 10.0, 0.0),
-        2.0,
-        &diff_light,
-    )));
+    let sphere_light: HittablePtr =
+        Arc::new(Sphere::new(Point3::new(0.0, 10.0, 0.0), 2.0, &diff_light));
+    world.add(Arc::clone(&sphere_light));
+    lights.add(sphere_light);
 
-    world
+    (world, lights)
 }
 
-fn cornell_box() -> HittableList {
+fn cornell_box() -> (HittableList, HittableList) {
     let mut world = HittableList::new();
+    let mut lights = HittableList::new();
 
     let red: MaterialPtr = Arc::new(Lambertian::new_from_color(&Color::new(0.65, 0.05, 0.05)));
     let white: MaterialPtr = Arc::new(Lambertian::new_from_color(&Color::new(0.73, 0.73, 0.73)));
@@ -348,9 +403,9 @@ fn cornell_box() -> HittableList {
 
     world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, &green)));
     world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, &red)));
-    world.add(Arc::new(XZRect::new(
-        213.0, 343.0, 227.0, 332.0, 554.0, &light,
-    )));
+    let light: HittablePtr = Arc::new(XZRect::new(213.0, 343.0, 227.0, 332.0, 554.0, &light));
+    world.add(Arc::clone(&light));
+    lights.add(light);
     world.add(Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, &white)));
     world.add(Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, &white)));
     world.add(Arc::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, &white)));
@@ -377,11 +432,12 @@ fn cornell_box() -> HittableList {
 
     world.add(box2);
 
-    world
+    (world, lights)
 }
 
-fn cornell_smoke() -> HittableList {
+fn cornell_smoke() -> (HittableList, HittableList) {
     let mut world = HittableList::new();
+    let mut lights = HittableList::new();
 
     let red: MaterialPtr = Arc::new(Lambertian::new_from_color(&Color::new(0.65, 0.05, 0.05)));
     let white: MaterialPtr = Arc::new(Lambertian::new_from_color(&Color::new(0.73, 0.73, 0.73)));
@@ -390,9 +446,9 @@ fn cornell_smoke() -> HittableList {
 
     world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, &green)));
     world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, &red)));
-    world.add(Arc::new(XZRect::new(
-        113.0, 443.0, 127.0, 432.0, 554.0, &light,
-    )));
+    let light: HittablePtr = Arc::new(XZRect::new(113.0, 443.0, 127.0, 432.0, 554.0, &light));
+    world.add(Arc::clone(&light));
+    lights.add(light);
     world.add(Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, &white)));
     world.add(Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, &white)));
     world.add(Arc::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, &white)));
@@ -426,10 +482,10 @@ fn cornell_smoke() -> HittableList {
         Color::new(1.0, 1.0, 1.0),
     )));
 
-    world
+    (world, lights)
 }
 
-fn final_scene() -> HittableList {
+fn final_scene() -> (HittableList, HittableList) {
     const RANDOM_SEED: u64 = 3;
 
     // Ground
@@ -458,13 +514,14 @@ fn final_scene() -> HittableList {
 
     let mut objects = HittableList::new();
 
-    objects.add(Arc::new(BVHNode::from_hittable_list(&boxes1, 0.0, 1.0)));
+    objects.add(Arc::new(Bvh::from_hittable_list(&boxes1, 0.0, 1.0)));
 
     // Light
+    let mut lights = HittableList::new();
     let light: MaterialPtr = Arc::new(DiffuseLight::new_from_color(&Color::new(7.0, 7.0, 7.0)));
-    objects.add(Arc::new(XZRect::new(
-        123.0, 423.0, 147.0, 412.0, 554.0, &light,
-    )));
+    let light: HittablePtr = Arc::new(XZRect::new(123.0, 423.0, 147.0, 412.0, 554.0, &light));
+    objects.add(Arc::clone(&light));
+    lights.add(light);
 
     // Moving sphere
     let center1 = Point3::new(400.0, 400.0, 400.0);
@@ -539,18 +596,62 @@ fn final_scene() -> HittableList {
     let ns = 1000;
     for _ in 0..ns {
         boxes2.add(Arc::new(Sphere::new(
-            Point3::random_range(0.0, 165.0),
+            Point3::random_range(0.0, 165.0, &mut rng),
             10.0,
             &white,
         )));
     }
 
-    let hittable: HittablePtr = Arc::new(BVHNode::from_hittable_list(&boxes2, 0.0, 1.0));
+    let hittable: HittablePtr = Arc::new(Bvh::from_hittable_list(&boxes2, 0.0, 1.0));
     let rotate_y: HittablePtr = Arc::new(RotateY::new(&hittable, 15.0));
     objects.add(Arc::new(Translate::new(
         &rotate_y,
         &Vec3::new(-100.0, 270.0, 395.0),
     )));
 
-    objects
+    (objects, lights)
+}
+
+fn mesh() -> HittableList {
+    let mut world = HittableList::new();
+
+    let checker: TexturePtr = Arc::new(CheckerTexture::new_from_colors(
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ));
+    let ground: MaterialPtr = Arc::new(Lambertian::new(&checker));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1001.0, 0.0),
+        1000.0,
+        &ground,
+    )));
+
+    let earth_texture: TexturePtr = Arc::new(ImageTexture::new("earthmap.jpg"));
+    let earth_surface: MaterialPtr = Arc::new(Lambertian::new(&earth_texture));
+    match TriangleMesh::load_obj("icosphere.obj", &earth_surface) {
+        Ok(mesh) => world.add(Arc::new(mesh)),
+        Err(e) => eprintln!("ERROR: Could not load mesh file icosphere.obj: {e}"),
+    }
+
+    world
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_height() {
+        // A height given by its aspect ratio with the width comes back whole.
+        for width in [2, 640, 800, 1024, 1280, 1920] {
+            for height in 2..1200 {
+                let aspect_ratio = width as f64 / height as f64;
+                assert_eq!(
+                    image_height(width, aspect_ratio),
+                    height,
+                    "{width} x {height}"
+                );
+            }
+        }
+    }
 }
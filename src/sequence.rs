@@ -0,0 +1,180 @@
+use std::{error::Error, fs, ops::Range, path::Path, path::PathBuf};
+
+use crate::render::{self, RenderConfig};
+
+/// Frames of an animation to render. Frame `n` starts at `n / fps` seconds
+/// of scene time.
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    pub frames: Range<u32>,
+    pub fps: f64,
+    /// Fraction of each frame's duration the shutter stays open.
+    pub shutter: f64,
+    /// Leave frames whose output file already exists, to resume an
+    /// interrupted render.
+    pub skip_existing: bool,
+}
+
+impl Sequence {
+    /// Scene times at which the shutter opens and closes for `frame`.
+    pub fn shutter_interval(&self, frame: u32) -> (f64, f64) {
+        let open = frame as f64 / self.fps;
+        (open, open + self.shutter / self.fps)
+    }
+
+    /// Scene times at which the shutter first opens and last closes.
+    ///
+    /// Panics if there are no frames.
+    pub fn time_span(&self) -> (f64, f64) {
+        let last = self
+            .frames
+            .end
+            .checked_sub(1)
+            .expect("Sequence should have frames");
+        let (open, _) = self.shutter_interval(self.frames.start);
+        let (_, close) = self.shutter_interval(last);
+        (open, close)
+    }
+}
+
+/// Parse a frame range such as `0..120`, `0..=119` or a single frame `7`.
+pub fn parse_frame_range(value: &str) -> Result<Range<u32>, String> {
+    let number = |s: &str| {
+        s.trim()
+            .parse::<u32>()
+            .map_err(|e| format!("invalid frame number '{s}': {e}"))
+    };
+
+    // The end of a range whose last frame is `last`.
+    let after = |last: u32| {
+        last.checked_add(1)
+            .ok_or_else(|| format!("frame {last} is too large"))
+    };
+
+    let range = if let Some((start, end)) = value.split_once("..=") {
+        number(start)?..after(number(end)?)?
+    } else if let Some((start, end)) = value.split_once("..") {
+        number(start)?..number(end)?
+    } else {
+        let frame = number(value)?;
+        frame..after(frame)?
+    };
+
+    if range.is_empty() {
+        return Err("the range contains no frames".to_string());
+    }
+    Ok(range)
+}
+
+/// Substitute `frame` for the printf-style `%d` or `%0<width>d` in the file
+/// name of `pattern`, e.g. `out_%04d.png` becomes `out_0007.png`.
+pub fn frame_file_name(pattern: &Path, frame: u32) -> Result<PathBuf, String> {
+    let file_name = pattern
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("'{}' is not a valid file name", pattern.display()))?;
+
+    let missing = || {
+        format!(
+            "'{}' needs a frame number pattern such as %04d",
+            pattern.display()
+        )
+    };
+    let start = file_name.find('%').ok_or_else(missing)?;
+    let end = start
+        + 1
+        + file_name[start + 1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(missing)?;
+    if !file_name[end..].starts_with('d') {
+        return Err(missing());
+    }
+
+    let width: usize = file_name[start + 1..end].parse().unwrap_or(0);
+    let name = format!(
+        "{}{frame:0width$}{}",
+        &file_name[..start],
+        &file_name[end + 1..]
+    );
+    Ok(pattern.with_file_name(name))
+}
+
+/// Render and save every frame of `sequence`, naming the files after the
+/// patterns in `config`.
+///
+/// Each frame is written to a temporary file and renamed once complete, so
+/// an interrupted job never leaves a truncated frame that `skip_existing`
+/// would mistake for a finished one.
+pub fn render_sequence(config: RenderConfig, sequence: &Sequence) -> Result<(), Box<dyn Error>> {
+    if sequence.fps <= 0.0 {
+        return Err("fps must be positive".into());
+    }
+    if !(0.0..=1.0).contains(&sequence.shutter) {
+        return Err("shutter must be between 0 and 1".into());
+    }
+
+    for frame in sequence.frames.clone() {
+        let file_name = frame_file_name(&config.file_name, frame)?;
+        if sequence.skip_existing && file_name.exists() {
+            eprintln!("Skipping frame {frame}: {} exists", file_name.display());
+            continue;
+        }
+        eprintln!("Frame {frame} -> {}", file_name.display());
+
+        let partial_file = partial_file_name(&file_name);
+        let mut frame_config = config.clone();
+        frame_config.file_name = partial_file.clone();
+        let (open, close) = sequence.shutter_interval(frame);
+        frame_config.scene.set_shutter(open, close);
+        if let Some(pattern) = &config.heatmap_file {
+            frame_config.heatmap_file = Some(frame_file_name(pattern, frame)?);
+        }
+        render::render_and_save(frame_config)?;
+        fs::rename(&partial_file, &file_name)?;
+        eprintln!();
+    }
+
+    Ok(())
+}
+
+/// `out_0001.png` becomes `out_0001.partial.png`, keeping the extension
+/// that selects the image format.
+fn partial_file_name(file_name: &Path) -> PathBuf {
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    match file_name.extension() {
+        Some(extension) => {
+            file_name.with_file_name(format!("{stem}.partial.{}", extension.to_string_lossy()))
+        }
+        None => file_name.with_file_name(format!("{stem}.partial")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_range_and_file_names() {
+        assert_eq!(parse_frame_range("0..120"), Ok(0..120));
+        assert_eq!(parse_frame_range("10..=12"), Ok(10..13));
+        assert_eq!(parse_frame_range("7"), Ok(7..8));
+        assert!(parse_frame_range("5..5").is_err());
+        assert!(parse_frame_range("a..5").is_err());
+        assert!(parse_frame_range("0..=4294967295").is_err());
+        assert!(parse_frame_range("4294967295").is_err());
+
+        let file_name = |pattern: &str, frame| frame_file_name(Path::new(pattern), frame);
+        assert_eq!(
+            file_name("renders/out_%04d.png", 7),
+            Ok(PathBuf::from("renders/out_0007.png"))
+        );
+        assert_eq!(file_name("%d.exr", 42), Ok(PathBuf::from("42.exr")));
+        assert!(file_name("out.png", 1).is_err());
+        assert!(file_name("out_%04x.png", 1).is_err());
+
+        assert_eq!(
+            partial_file_name(Path::new("renders/out_0007.png")),
+            PathBuf::from("renders/out_0007.partial.png")
+        );
+    }
+}
@@ -2,8 +2,13 @@ use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     aabb::Aabb,
-    hittable, material,
-    vec3::{Point3, Vec3},
+    hittable::{self, HitRecord},
+    inspector::{self, Node, Parameter, Part},
+    material,
+    onb::Onb,
+    ray::Ray,
+    rng::{self, RenderRng},
+    vec3::{self, Point3, Vec3},
 };
 
 pub struct Sphere {
@@ -41,6 +46,7 @@ impl hittable::Hittable for Sphere {
         t_min: f64,
         t_max: f64,
         rec: &mut hittable::HitRecord,
+        _rng: &mut RenderRng,
     ) -> bool {
         let oc = ray.origin - self.center;
         let a = ray.direction.length_squared();
@@ -79,4 +85,54 @@ impl hittable::Hittable for Sphere {
         );
         Some(output_box)
     }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        // This method only works for stationary spheres.
+        let mut rec = HitRecord::empty();
+        if !self.hit(
+            &Ray::new(*origin, *direction, time),
+            0.001,
+            f64::INFINITY,
+            &mut rec,
+            &mut rng::unused_rng(),
+        ) {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).length_squared();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, rng: &mut RenderRng) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        let uvw = Onb::build_from_w(&direction);
+        uvw.local(&vec3::random_to_sphere(self.radius, distance_squared, rng))
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let radius = self.radius;
+        Some(Node::new(
+            "Sphere",
+            vec![
+                Parameter::point("Center", self.center),
+                Parameter::unbounded("Radius", self.radius),
+            ],
+            vec![inspector::material("Material", &self.material)],
+            move |values, parts| {
+                Part::Object(Arc::new(Sphere::new(
+                    values[0].point(),
+                    inspector::nonzero(values[1].number(), radius),
+                    &parts[0].material(),
+                )))
+            },
+        ))
+    }
 }
@@ -1,12 +1,26 @@
 use std::sync::Arc;
 
 use crate::{
+    color,
+    inspector::{self, Node, Parameter, Part},
     perlin::Perlin,
     vec3::{Color, Point3},
 };
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, point: &Point3) -> Color;
+
+    /// The texture as a single number, such as a roughness: the luminance of
+    /// its color, which is the value of a grey one.
+    fn scalar_value(&self, u: f64, v: f64, point: &Point3) -> f64 {
+        color::luminance(&self.value(u, v, point))
+    }
+
+    /// What the scene inspector shows of this texture, or `None` if it can't
+    /// look into it.
+    fn inspect(&self) -> Option<Node> {
+        None
+    }
 }
 
 pub type TexturePtr = Arc<dyn Texture>;
@@ -24,12 +38,26 @@ impl SolidColor {
         let color = Color::new(r, g, b);
         SolidColor::new(color)
     }
+
+    /// Texture of a single value, such as a roughness.
+    pub fn new_grey(value: f64) -> SolidColor {
+        SolidColor::new_from_rgb(value, value, value)
+    }
 }
 
 impl Texture for SolidColor {
     fn value(&self, _u: f64, _v: f64, _point: &Point3) -> Color {
         self.color_value
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Solid color",
+            vec![Parameter::color("Color", self.color_value)],
+            vec![],
+            |values, _| Part::Texture(Arc::new(SolidColor::new(values[0].color()))),
+        ))
+    }
 }
 
 pub struct CheckerTexture {
@@ -60,6 +88,23 @@ impl Texture for CheckerTexture {
             self.even.value(u, v, point)
         }
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Checker",
+            vec![],
+            vec![
+                inspector::texture("Even", &self.even),
+                inspector::texture("Odd", &self.odd),
+            ],
+            |_, parts| {
+                Part::Texture(Arc::new(CheckerTexture::new(
+                    &parts[0].texture(),
+                    &parts[1].texture(),
+                )))
+            },
+        ))
+    }
 }
 
 pub struct NoiseTexture {
@@ -70,7 +115,7 @@ pub struct NoiseTexture {
 impl NoiseTexture {
     pub fn new(scale: f64) -> NoiseTexture {
         NoiseTexture {
-            noise: Perlin::new(),
+            noise: Perlin::default(),
             scale,
         }
     }
@@ -82,6 +127,15 @@ impl Texture for NoiseTexture {
             * 0.5
             * (1.0 + (self.scale * point.z() + 10.0 * self.noise.turbulence(point, 7)).sin())
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::new(
+            "Noise",
+            vec![Parameter::number("Scale", self.scale, 0.0)],
+            vec![],
+            |values, _| Part::Texture(Arc::new(NoiseTexture::new(values[0].number()))),
+        ))
+    }
 }
 
 pub struct ImageTexture {
@@ -157,4 +211,8 @@ impl Texture for ImageTexture {
             color_scale * self.data[pixel_index + 2] as f64,
         )
     }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::opaque("Image"))
+    }
 }
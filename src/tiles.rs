@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Width and height of the square tiles an image is rendered in.
+pub const TILE_SIZE: u32 = 32;
+
+/// Order in which the tiles of an image are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    #[default]
+    Scanline,
+    /// Outwards from the center, which usually holds the subject.
+    Spiral,
+    /// Along a Hilbert curve, keeping consecutive tiles close together.
+    Hilbert,
+}
+
+/// Rectangle of pixels rendered as one unit of work, in image coordinates
+/// with `y` going down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Split an image into tiles of at most `tile_size` pixels square, sorted in
+/// `order`. Tiles at the right and bottom edges may be smaller.
+pub fn tiles(image_width: u32, image_height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let columns = image_width.div_ceil(tile_size);
+    let rows = image_height.div_ceil(tile_size);
+    let tile = |(column, row): (u32, u32)| {
+        let (x, y) = (column * tile_size, row * tile_size);
+        Tile {
+            x,
+            y,
+            width: tile_size.min(image_width - x),
+            height: tile_size.min(image_height - y),
+        }
+    };
+
+    let cells: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            (0..side * side)
+                .map(|d| hilbert_cell(side, d))
+                .filter(|&(column, row)| column < columns && row < rows)
+                .collect()
+        }
+    };
+    cells.into_iter().map(tile).collect()
+}
+
+/// Walk a square spiral out from the middle cell of the grid, keeping the
+/// cells that fall inside it.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let count = (columns * rows) as usize;
+    let mut cells = Vec::with_capacity(count);
+    let (mut column, mut row) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 0;
+
+    while cells.len() < count {
+        // Legs grow by one every second turn: 1, 1, 2, 2, 3, 3, ...
+        let (dx, dy) = directions[leg % 4];
+        for _ in 0..leg / 2 + 1 {
+            if (0..columns as i64).contains(&column) && (0..rows as i64).contains(&row) {
+                cells.push((column as u32, row as u32));
+            }
+            column += dx;
+            row += dy;
+        }
+        leg += 1;
+    }
+    cells
+}
+
+/// Cell at distance `d` along the Hilbert curve filling a `side` by `side`
+/// grid, `side` being a power of two.
+fn hilbert_cell(side: u32, d: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            (x, y) = (y, x);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+/// Tiles shared by the render threads, each taking the next one as soon as
+/// it is done with its last, so no thread idles while work remains.
+///
+/// A progressive render goes over every tile once per pass; all tiles of a
+/// pass are handed out before any of the next.
+pub struct TileQueue {
+    tiles: Vec<Tile>,
+    passes: usize,
+    next: AtomicUsize,
+}
+
+impl TileQueue {
+    pub fn new(tiles: Vec<Tile>, passes: usize) -> TileQueue {
+        TileQueue {
+            tiles,
+            passes,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of tiles handed out over all passes.
+    pub fn len(&self) -> usize {
+        self.tiles.len() * self.passes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Take the next tile and the index of its pass, or `None` once all have
+    /// been handed out.
+    pub fn pop(&self) -> Option<(usize, Tile)> {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        if next >= self.len() {
+            return None;
+        }
+        Some((next / self.tiles.len(), self.tiles[next % self.tiles.len()]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_order_covers_the_image() {
+        let (width, height) = (200, 90);
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = tiles(width, height, TILE_SIZE, order);
+            assert_eq!(tiles.len(), 7 * 3, "{order:?}");
+
+            let mut covered = vec![0; (width * height) as usize];
+            for tile in &tiles {
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        covered[(y * width + x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&c| c == 1), "{order:?}");
+        }
+
+        let spiral = tiles(width, height, TILE_SIZE, TileOrder::Spiral);
+        assert_eq!((spiral[0].x, spiral[0].y), (96, 32));
+
+        // Consecutive tiles along the Hilbert curve are neighbours.
+        let hilbert = tiles(128, 128, TILE_SIZE, TileOrder::Hilbert);
+        for pair in hilbert.windows(2) {
+            let distance = pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y);
+            assert_eq!(distance, TILE_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_queue_hands_out_passes_in_order() {
+        let queue = TileQueue::new(tiles(64, 32, TILE_SIZE, TileOrder::Scanline), 2);
+        assert_eq!(queue.len(), 4);
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop())
+            .map(|(pass, tile)| (pass, tile.x))
+            .collect();
+        assert_eq!(popped, [(0, 0), (0, 32), (1, 0), (1, 32)]);
+        assert_eq!(queue.pop(), None);
+    }
+}
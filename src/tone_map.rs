@@ -0,0 +1,147 @@
+use image::{Rgb, Rgb32FImage, RgbImage};
+
+/// Curve compressing linear radiance into the displayable 0..1 range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ToneMapOperator {
+    /// Cut off everything above 1.
+    Clamp,
+    /// `x / (1 + x)`, which never quite reaches white.
+    Reinhard,
+    /// Reinhard scaled so that the white point maps to 1.
+    ExtendedReinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Uncharted2,
+}
+
+/// Settings for turning a linear render into 8-bit sRGB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// Exposure adjustment in stops, applied before the operator.
+    pub exposure: f32,
+    /// Smallest radiance mapped to white by `ExtendedReinhard`.
+    pub white_point: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapOperator, exposure: f32, white_point: f32) -> ToneMapping {
+        ToneMapping {
+            operator,
+            exposure,
+            white_point,
+        }
+    }
+
+    /// Expose, tone map and sRGB encode a linear color.
+    pub fn map(&self, pixel: Rgb<f32>) -> Rgb<u8> {
+        let scale = self.exposure.exp2();
+        pixel
+            .0
+            .map(|c| {
+                let mapped = self.map_channel((c * scale).max(0.0));
+                (255.0 * linear_to_srgb(mapped.clamp(0.0, 1.0)) + 0.5) as u8
+            })
+            .into()
+    }
+
+    pub fn apply(&self, image: &Rgb32FImage) -> RgbImage {
+        RgbImage::from_fn(image.width(), image.height(), |x, y| {
+            self.map(*image.get_pixel(x, y))
+        })
+    }
+
+    fn map_channel(&self, x: f32) -> f32 {
+        match self.operator {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                x * (1.0 + x / white_squared) / (1.0 + x)
+            }
+            ToneMapOperator::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            ToneMapOperator::Uncharted2 => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const LINEAR_WHITE: f32 = 11.2;
+                uncharted2_curve(EXPOSURE_BIAS * x) / uncharted2_curve(LINEAR_WHITE)
+            }
+        }
+    }
+}
+
+fn uncharted2_curve(x: f32) -> f32 {
+    const SHOULDER_STRENGTH: f32 = 0.15;
+    const LINEAR_STRENGTH: f32 = 0.5;
+    const LINEAR_ANGLE: f32 = 0.1;
+    const TOE_STRENGTH: f32 = 0.2;
+    const TOE_NUMERATOR: f32 = 0.02;
+    const TOE_DENOMINATOR: f32 = 0.3;
+
+    (x * (SHOULDER_STRENGTH * x + LINEAR_ANGLE * LINEAR_STRENGTH) + TOE_STRENGTH * TOE_NUMERATOR)
+        / (x * (SHOULDER_STRENGTH * x + LINEAR_STRENGTH) + TOE_STRENGTH * TOE_DENOMINATOR)
+        - TOE_NUMERATOR / TOE_DENOMINATOR
+}
+
+/// The sRGB transfer function, for a linear value in 0..1.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMapOperator; 5] = [
+        ToneMapOperator::Clamp,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::ExtendedReinhard,
+        ToneMapOperator::Aces,
+        ToneMapOperator::Uncharted2,
+    ];
+
+    #[test]
+    fn test_operators_are_monotonic() {
+        for operator in OPERATORS {
+            let tone_mapping = ToneMapping::new(operator, 0.0, 4.0);
+            assert_eq!(tone_mapping.map(Rgb([0.0, 0.0, 0.0])), Rgb([0, 0, 0]));
+
+            let mut previous = 0;
+            for i in 0..100 {
+                let value = tone_mapping.map(Rgb([i as f32 * 0.1, 0.0, 0.0]))[0];
+                assert!(value >= previous, "{operator:?} decreases at {i}");
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn test_exposure_and_white_point() {
+        let clamp = ToneMapping::default();
+        assert_eq!(clamp.map(Rgb([1.0, 0.5, 2.0])), Rgb([255, 188, 255]));
+
+        let darker = ToneMapping::new(ToneMapOperator::Clamp, -1.0, 4.0);
+        assert_eq!(
+            darker.map(Rgb([1.0, 1.0, 1.0])),
+            clamp.map(Rgb([0.5, 0.5, 0.5]))
+        );
+
+        let extended = ToneMapping::new(ToneMapOperator::ExtendedReinhard, 0.0, 4.0);
+        assert_eq!(extended.map(Rgb([4.0, 4.0, 4.0])), Rgb([255, 255, 255]));
+        assert!(extended.map(Rgb([2.0, 2.0, 2.0]))[0] < 255);
+    }
+}
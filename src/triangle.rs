@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    inspector::{self, Node, Part},
+    material::MaterialPtr,
+    ray::Ray,
+    rng::RenderRng,
+    vec3::{self, Point3, Vec3},
+};
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    tex_coords: Option<[(f64, f64); 3]>,
+    material: MaterialPtr,
+}
+
+impl Triangle {
+    /// Triangle with optional per-vertex normals and texture coordinates.
+    ///
+    /// Normals are interpolated for smooth shading. Texture coordinates are
+    /// interpolated into `HitRecord::u` and `HitRecord::v`; without them the
+    /// barycentric coordinates of the hit are stored there instead.
+    pub fn new(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        tex_coords: Option<[(f64, f64); 3]>,
+        material: &MaterialPtr,
+    ) -> Triangle {
+        Triangle {
+            vertices,
+            normals,
+            tex_coords,
+            material: Arc::clone(material),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _rng: &mut RenderRng,
+    ) -> bool {
+        // Möller-Trumbore ray-triangle intersection.
+        const EPSILON: f64 = 1e-12;
+
+        let [v0, v1, v2] = self.vertices;
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
+
+        let p_vec = ray.direction.cross(&edge2);
+        let det = edge1.dot(&p_vec);
+        // The ray is parallel to the triangle.
+        if det.abs() < EPSILON {
+            return false;
+        }
+        let inv_det = 1.0 / det;
+
+        let t_vec = ray.origin - v0;
+        let u = t_vec.dot(&p_vec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return false;
+        }
+
+        let q_vec = t_vec.cross(&edge1);
+        let v = ray.direction.dot(&q_vec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return false;
+        }
+
+        let t = edge2.dot(&q_vec) * inv_det;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        let w = 1.0 - u - v;
+        let outward_normal = match &self.normals {
+            Some([n0, n1, n2]) => vec3::unit_vector(&(w * n0 + u * n1 + v * n2)),
+            None => vec3::unit_vector(&edge1.cross(&edge2)),
+        };
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.set_face_normal(ray, &outward_normal);
+        rec.material = Some(Arc::clone(&self.material));
+        (rec.u, rec.v) = match &self.tex_coords {
+            Some([uv0, uv1, uv2]) => (
+                w * uv0.0 + u * uv1.0 + v * uv2.0,
+                w * uv0.1 + u * uv1.1 + v * uv2.1,
+            ),
+            None => (u, v),
+        };
+
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        // The bounding box must have non-zero width in each dimension,
+        // so pad axis-aligned triangles a small amount.
+        let padding = 0.0001;
+        let [v0, v1, v2] = self.vertices;
+
+        let mut min = v0;
+        let mut max = v0;
+        for vertex in [v1, v2] {
+            for a in 0..3 {
+                min[a] = min[a].min(vertex[a]);
+                max[a] = max[a].max(vertex[a]);
+            }
+        }
+        for a in 0..3 {
+            if max[a] - min[a] < padding {
+                min[a] -= padding;
+                max[a] += padding;
+            }
+        }
+
+        Some(Aabb::new(min, max))
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        let (vertices, normals, tex_coords) = (self.vertices, self.normals, self.tex_coords);
+        Some(Node::new(
+            "Triangle",
+            vec![],
+            vec![inspector::material("Material", &self.material)],
+            move |_, parts| {
+                Part::Object(Arc::new(Triangle::new(
+                    vertices,
+                    normals,
+                    tex_coords,
+                    &parts[0].material(),
+                )))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{material::Lambertian, rng, vec3::Color};
+
+    use super::*;
+
+    fn unit_triangle() -> Triangle {
+        let material: MaterialPtr =
+            Arc::new(Lambertian::new_from_color(&Color::new(0.5, 0.5, 0.5)));
+        Triangle::new(
+            [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            None,
+            None,
+            &material,
+        )
+    }
+
+    #[test]
+    fn test_triangle_hit() {
+        let triangle = unit_triangle();
+        let ray = Ray::new_at_t0(Point3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::empty();
+
+        assert!(
+            triangle.hit(&ray, 0.0, 10.0, &mut rec, &mut rng::unused_rng()),
+            "Ray didn't hit"
+        );
+        assert!((rec.t - 1.0).abs() < 1e-9);
+        assert!((rec.u - 0.25).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+        assert!(rec.front_face);
+    }
+
+    #[test]
+    fn test_triangle_miss() {
+        let triangle = unit_triangle();
+        let mut rec = HitRecord::empty();
+
+        let outside = Ray::new_at_t0(Point3::new(0.75, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(
+            !triangle.hit(&outside, 0.0, 10.0, &mut rec, &mut rng::unused_rng()),
+            "Hit outside edge"
+        );
+
+        let parallel = Ray::new_at_t0(Point3::new(-1.0, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(
+            !triangle.hit(&parallel, 0.0, 10.0, &mut rec, &mut rng::unused_rng()),
+            "Parallel ray hit"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::{error::Error, path::Path, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    hittable::{HitRecord, Hittable, HittablePtr},
+    inspector::Node,
+    material::MaterialPtr,
+    ray::Ray,
+    rng::RenderRng,
+    triangle::Triangle,
+    vec3::{Point3, Vec3},
+};
+
+/// A collection of triangles behind its own BVH, so that a large mesh can be
+/// added to a `HittableList` as a single object.
+pub struct TriangleMesh {
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(triangles: &[HittablePtr]) -> TriangleMesh {
+        assert!(
+            !triangles.is_empty(),
+            "TriangleMesh needs at least one triangle"
+        );
+        TriangleMesh {
+            bvh: Bvh::new(triangles, 0.0, 1.0),
+        }
+    }
+
+    /// Load every group of a Wavefront OBJ file into one mesh.
+    ///
+    /// Faces are triangulated and vertex normals and texture coordinates are
+    /// used when the file provides them. All faces share `material`.
+    pub fn load_obj(
+        file_name: impl AsRef<Path>,
+        material: &MaterialPtr,
+    ) -> Result<TriangleMesh, Box<dyn Error>> {
+        let file_name = file_name.as_ref();
+        let load_options = tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        };
+        let (models, _materials) = tobj::load_obj(file_name, &load_options)?;
+
+        let mut triangles: Vec<HittablePtr> = Vec::new();
+        for model in &models {
+            let mesh = &model.mesh;
+            let position = |i: usize| {
+                Point3::new(
+                    mesh.positions[3 * i] as f64,
+                    mesh.positions[3 * i + 1] as f64,
+                    mesh.positions[3 * i + 2] as f64,
+                )
+            };
+            let normal = |i: usize| {
+                Vec3::new(
+                    mesh.normals[3 * i] as f64,
+                    mesh.normals[3 * i + 1] as f64,
+                    mesh.normals[3 * i + 2] as f64,
+                )
+            };
+            let tex_coord = |i: usize| {
+                (
+                    mesh.texcoords[2 * i] as f64,
+                    mesh.texcoords[2 * i + 1] as f64,
+                )
+            };
+
+            for face in mesh.indices.chunks_exact(3) {
+                let [i0, i1, i2] = [face[0] as usize, face[1] as usize, face[2] as usize];
+
+                let normals = if mesh.normals.is_empty() {
+                    None
+                } else {
+                    Some([normal(i0), normal(i1), normal(i2)])
+                };
+                let tex_coords = if mesh.texcoords.is_empty() {
+                    None
+                } else {
+                    Some([tex_coord(i0), tex_coord(i1), tex_coord(i2)])
+                };
+
+                triangles.push(Arc::new(Triangle::new(
+                    [position(i0), position(i1), position(i2)],
+                    normals,
+                    tex_coords,
+                    material,
+                )));
+            }
+        }
+
+        if triangles.is_empty() {
+            return Err(format!("No triangles found in {}", file_name.display()).into());
+        }
+
+        Ok(TriangleMesh::new(&triangles))
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        rng: &mut RenderRng,
+    ) -> bool {
+        self.bvh.hit(ray, t_min, t_max, rec, rng)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.bvh.bounding_box(time0, time1)
+    }
+
+    fn inspect(&self) -> Option<Node> {
+        Some(Node::opaque("Triangle mesh"))
+    }
+}
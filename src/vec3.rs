@@ -1,4 +1,4 @@
-use std::ops;
+use std::{f64::consts::PI, ops};
 
 use rand::Rng;
 
@@ -16,13 +16,11 @@ impl Vec3 {
         Vec3 { e: [e0, e1, e2] }
     }
 
-    pub fn random() -> Vec3 {
-        let mut rng = rand::thread_rng();
+    pub fn random(rng: &mut impl Rng) -> Vec3 {
         Vec3::new(rng.gen(), rng.gen(), rng.gen())
     }
 
-    pub fn random_range(min: f64, max: f64) -> Vec3 {
-        let mut rng = rand::thread_rng();
+    pub fn random_range(min: f64, max: f64, rng: &mut impl Rng) -> Vec3 {
         Vec3::new(
             rng.gen_range(min..max),
             rng.gen_range(min..max),
@@ -78,21 +76,21 @@ pub fn unit_vector(v: &Vec3) -> Vec3 {
     v / v.length()
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
+pub fn random_in_unit_sphere(rng: &mut impl Rng) -> Vec3 {
     loop {
-        let p = Vec3::random_range(-1.0, 1.0);
+        let p = Vec3::random_range(-1.0, 1.0, rng);
         if p.length_squared() < 1.0 {
             return p;
         }
     }
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(&random_in_unit_sphere())
+pub fn random_unit_vector(rng: &mut impl Rng) -> Vec3 {
+    unit_vector(&random_in_unit_sphere(rng))
 }
 
-pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
+pub fn random_in_hemisphere(normal: &Vec3, rng: &mut impl Rng) -> Vec3 {
+    let in_unit_sphere = random_in_unit_sphere(rng);
     if in_unit_sphere.dot(normal) > 0.0 {
         in_unit_sphere
     } else {
@@ -100,8 +98,7 @@ pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
     }
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
+pub fn random_in_unit_disk(rng: &mut impl Rng) -> Vec3 {
     loop {
         let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
         if p.length_squared() < 1.0 {
@@ -110,6 +107,34 @@ pub fn random_in_unit_disk() -> Vec3 {
     }
 }
 
+/// Random direction around +Z, distributed proportional to the cosine of the
+/// angle from +Z.
+pub fn random_cosine_direction(rng: &mut impl Rng) -> Vec3 {
+    let r1: f64 = rng.gen();
+    let r2: f64 = rng.gen();
+    let z = (1.0 - r2).sqrt();
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+
+    Vec3::new(x, y, z)
+}
+
+/// Random direction around +Z towards a sphere of `radius` whose center is
+/// at `distance_squared` from the origin.
+pub fn random_to_sphere(radius: f64, distance_squared: f64, rng: &mut impl Rng) -> Vec3 {
+    let r1: f64 = rng.gen();
+    let r2: f64 = rng.gen();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+
+    Vec3::new(x, y, z)
+}
+
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     v - 2.0 * v.dot(n) * n
 }